@@ -0,0 +1,106 @@
+use crate::prelude::*;
+use crate::scene::{ColorItem, Contains};
+use rand::rngs::StdRng;
+use std::sync::Arc;
+
+/// How the color of an item is combined with the colors below it
+#[derive(Debug, Clone, Copy)]
+pub enum BlendMode {
+    /// Item color replaces what is below
+    Normal,
+    /// Always darker, white is neutral
+    Multiply,
+    /// Always lighter, black is neutral
+    Screen,
+    /// Multiply dark areas and screen light areas of what is below
+    Overlay,
+    /// Mean of the two colors
+    Average,
+}
+
+impl BlendMode {
+    pub fn from(s: &str) -> Option<Self> {
+        match s {
+            "normal" => Some(BlendMode::Normal),
+            "multiply" => Some(BlendMode::Multiply),
+            "screen" => Some(BlendMode::Screen),
+            "overlay" => Some(BlendMode::Overlay),
+            "average" => Some(BlendMode::Average),
+            _ => None,
+        }
+    }
+
+    /// Combine one channel, both values within [0; 1]
+    fn channel(self, below: f64, above: f64) -> f64 {
+        match self {
+            BlendMode::Normal => above,
+            BlendMode::Multiply => below * above,
+            BlendMode::Screen => 1. - (1. - below) * (1. - above),
+            BlendMode::Overlay => {
+                if below < 0.5 {
+                    2. * below * above
+                } else {
+                    1. - 2. * (1. - below) * (1. - above)
+                }
+            }
+            BlendMode::Average => (below + above) / 2.,
+        }
+    }
+}
+
+/// Composition of an item with the items below it: when set, the item
+/// lets those below contribute to the color instead of hiding them
+#[derive(Debug, Clone, Copy)]
+pub struct Blend {
+    pub mode: BlendMode,
+    /// 0 for invisible items, 1 for fully applying the blend mode
+    pub opacity: f64,
+}
+
+impl Blend {
+    /// Paint `above` over `below`
+    pub fn apply(&self, below: Color, above: Color) -> Color {
+        let (b, a) = (below.to_f64(), above.to_f64());
+        let mix = |b: f64, a: f64| {
+            let blended = self.mode.channel(b / 255., a / 255.) * 255.;
+            b + (blended - b) * self.opacity
+        };
+        Color::from_f64((mix(b.0, a.0), mix(b.1, a.1), mix(b.2, a.2)))
+    }
+}
+
+/// Item painted over what is below it with a blend mode instead of hiding it
+pub struct Blended {
+    pub inner: Arc<dyn Contains>,
+    pub blend: Blend,
+}
+
+impl Contains for Blended {
+    fn hit(&self, p: Pos) -> bool {
+        self.inner.hit(p)
+    }
+
+    fn color(&self) -> &ColorItem {
+        self.inner.color()
+    }
+
+    fn contains(&self, p: Pos, rng: &mut StdRng) -> Option<Color> {
+        self.inner.contains(p, rng)
+    }
+
+    fn relief(&self, p: Pos) -> Pos {
+        self.inner.relief(p)
+    }
+
+    fn bbox(&self) -> Option<(Pos, Pos)> {
+        self.inner.bbox()
+    }
+
+    fn edge(&self, p: Pos) -> Option<f64> {
+        self.inner.edge(p)
+    }
+
+    fn blend(&self) -> Option<Blend> {
+        Some(self.blend)
+    }
+}
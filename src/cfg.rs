@@ -1,27 +1,92 @@
+use crate::blend::{Blend, Blended};
+use crate::coverage::Coverage;
+use crate::csg::{Csg, Transform};
+use crate::feather::Feather;
+use crate::filter::Filter;
+use crate::lighting::Lighting;
+use crate::noise::Noise;
 use crate::paint::*;
 use crate::prelude::*;
 use crate::scene::*;
 use crate::svg::*;
 use crate::tesselate::*;
-use rand::{rngs::ThreadRng, seq::SliceRandom, Rng};
-use std::rc::Rc;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
+use serde_derive::{Deserialize, Serialize};
+use std::sync::Arc;
 
 /// General information on a scene
+#[derive(Clone)]
 pub struct SceneCfg {
     pub theme: Chooser<ThemeItem>,
     pub distance: usize,
     pub deviation: usize,
     pub frame: Frame,
     pub pattern: Pattern,
+    pub params: PatternParams,
     pub tiling: Tiling,
-    pub nb_pattern: usize,
-    pub var_stripes: usize,
     pub size_tiling: f64,
     pub nb_delaunay: usize,
-    pub width_pattern: f64,
+    pub ratio_bricks: f64,
+    pub offset_bricks: f64,
     pub line_width: f64,
     pub line_color: Color,
+    pub noise: Option<Noise>,
+    pub lighting: Option<Lighting>,
+    pub filter: Filter,
+    pub blend: Option<Blend>,
+    pub coverage: Option<Coverage>,
+    pub csg: Option<Csg>,
+    pub transform: Option<Transform>,
+    pub feather: Option<Feather>,
+    pub background: Option<Background>,
+    pub layers: Vec<Layer>,
+}
+
+/// Settings of a pattern, read by the items of that pattern
+#[derive(Clone)]
+pub struct PatternParams {
+    pub nb_pattern: usize,
+    pub var_stripes: usize,
+    pub width_pattern: f64,
     pub tightness_spiral: f64,
+    pub weight_voronoi: f64,
+    pub metric_voronoi: Metric,
+    pub scale_contours: f64,
+    pub octaves_contours: usize,
+    pub terrace_contours: bool,
+    pub sides_polygons: (usize, usize),
+    pub star_polygons: f64,
+    pub rotation_polygons: isize,
+    pub size_polygons: (f64, f64),
+    pub distribution_polygons: SizeDistribution,
+    pub center_rays: RayCenter,
+    pub jitter_rays: f64,
+    pub twist_rays: isize,
+    pub alternate_rays: bool,
+    pub centers_arcs: usize,
+    pub gap_arcs: f64,
+    pub width_arcs: f64,
+    pub families_curves: Vec<CurveFamily>,
+    pub size_curves: (f64, f64),
+    pub petals_curves: (usize, usize),
+    pub width_curves: f64,
+    pub scale_flow: f64,
+    pub vortices_flow: usize,
+    pub width_flow: f64,
+    pub kind_fractal: Option<FractalKind>,
+    pub iterations_fractal: usize,
+    pub bands_gradient: bool,
+    pub jitter_gradient: f64,
+    pub rule_automaton: Rule,
+    pub steps_automaton: usize,
+}
+
+/// Pattern drawn above or below the main one, with its own theme and settings
+#[derive(Clone)]
+pub struct Layer {
+    /// Layers with a higher z are on top, the main pattern is at 0
+    pub z: isize,
+    pub cfg: SceneCfg,
 }
 
 /// A trait to box scene items and make them generic.
@@ -30,24 +95,34 @@ trait Dynamic<C>
 where
     C: Contains + 'static,
 {
-    fn dynamic(self) -> Vec<Rc<dyn Contains>>;
+    fn dynamic(self) -> Vec<Arc<dyn Contains>>;
 }
 
 impl<C> Dynamic<C> for Vec<C>
 where
     C: Contains + 'static,
 {
-    fn dynamic(self) -> Vec<Rc<dyn Contains>> {
+    fn dynamic(self) -> Vec<Arc<dyn Contains>> {
         self.into_iter()
-            .map(|d| Rc::new(d) as Rc<dyn Contains>)
+            .map(|d| Arc::new(d) as Arc<dyn Contains>)
             .collect::<Vec<_>>()
     }
 }
 
 impl SceneCfg {
+    /// Typical distance between neighboring tiles
+    pub fn tile_size(&self) -> f64 {
+        match self.tiling {
+            Tiling::Delaunay => {
+                ((self.frame.w * self.frame.h) as f64 / self.nb_delaunay.max(1) as f64).sqrt()
+            }
+            _ => self.size_tiling,
+        }
+    }
+
     /// Select a random color for a scene item.
     /// The actual color will depend on the Chooser<Color> with which it is mixed.
-    pub fn choose_color(&self, rng: &mut ThreadRng) -> ColorItem {
+    pub fn choose_color(&self, rng: &mut StdRng) -> ColorItem {
         let ThemeItem(c, v, w, salt) = self
             .theme
             .choose(rng)
@@ -57,27 +132,90 @@ impl SceneCfg {
             deviation: v.unwrap_or(self.deviation),
             distance: w.unwrap_or(self.distance),
             theme: c,
-            salt,
+            salt: salt.seed(rng, self.tile_size()),
+            noise: self.noise.map(|n| n.reseed(rng)),
         }
     }
 
+    /// Items of all layers, sorted from top to bottom
+    pub fn create_items(
+        &self,
+        rng: &mut StdRng,
+        tiles: &[(Pos, Path)],
+        verbose: Verbosity,
+    ) -> Vec<Arc<dyn Contains>> {
+        let mut layers = vec![(0, self)];
+        layers.extend(self.layers.iter().map(|l| (l.z, &l.cfg)));
+        // Among layers with the same z, the last one is on top
+        layers.sort_by_key(|&(z, _)| z);
+        let mut items = Vec::new();
+        for (_, cfg) in layers.into_iter().rev() {
+            items.extend(cfg.create_layer(rng, tiles, verbose));
+        }
+        items
+    }
+
     /// Match pattern to function that generates it
-    pub fn create_items(&self, rng: &mut ThreadRng, verbose: Verbosity) -> Vec<Rc<dyn Contains>> {
+    fn create_layer(
+        &self,
+        rng: &mut StdRng,
+        tiles: &[(Pos, Path)],
+        verbose: Verbosity,
+    ) -> Vec<Arc<dyn Contains>> {
+        let items = self.create_primitives(rng, tiles, verbose);
+        let items = match &self.csg {
+            None => items,
+            Some(csg) => csg.apply(items, &self.frame, rng),
+        };
+        let items = match &self.transform {
+            None => items,
+            Some(transform) => transform.apply(items, &self.frame, rng),
+        };
+        match self.blend {
+            None => items,
+            Some(blend) => items
+                .into_iter()
+                .map(|inner| Arc::new(Blended { inner, blend }) as Arc<dyn Contains>)
+                .collect(),
+        }
+    }
+
+    fn create_primitives(
+        &self,
+        rng: &mut StdRng,
+        tiles: &[(Pos, Path)],
+        verbose: Verbosity,
+    ) -> Vec<Arc<dyn Contains>> {
         match self.pattern {
-            Pattern::FreeCircles => create_free_circles(rng, &self, verbose).dynamic(),
-            Pattern::FreeTriangles => create_free_triangles(rng, &self, verbose).dynamic(),
-            Pattern::FreeStripes => create_free_stripes(rng, &self, verbose).dynamic(),
-            Pattern::FreeSpirals => create_free_spirals(rng, &self, verbose).dynamic(),
-            Pattern::ConcentricCircles => create_concentric_circles(rng, &self, verbose).dynamic(),
-            Pattern::ParallelStripes => create_parallel_stripes(rng, &self, verbose).dynamic(),
-            Pattern::CrossedStripes => create_crossed_stripes(rng, &self, verbose).dynamic(),
-            Pattern::ParallelWaves => create_waves(rng, &self, verbose).dynamic(),
-            Pattern::ParallelSawteeth => create_sawteeth(rng, &self, verbose).dynamic(),
+            Pattern::FreeCircles => create_free_circles(rng, self, verbose).dynamic(),
+            Pattern::FreeTriangles => create_free_triangles(rng, self, verbose).dynamic(),
+            Pattern::FreePolygons => create_free_polygons(rng, self, verbose).dynamic(),
+            Pattern::FreeStripes => create_free_stripes(rng, self, verbose).dynamic(),
+            Pattern::FreeSpirals => create_free_spirals(rng, self, verbose).dynamic(),
+            Pattern::Curves => create_curves(rng, self, verbose).dynamic(),
+            Pattern::ConcentricCircles => create_concentric_circles(rng, self, verbose).dynamic(),
+            Pattern::RadialRays => create_radial_rays(rng, self, verbose).dynamic(),
+            Pattern::Arcs => create_arcs(rng, self, verbose).dynamic(),
+            Pattern::ParallelStripes => create_parallel_stripes(rng, self, verbose).dynamic(),
+            Pattern::CrossedStripes => create_crossed_stripes(rng, self, verbose).dynamic(),
+            Pattern::ParallelWaves => create_waves(rng, self, verbose).dynamic(),
+            Pattern::ParallelSawteeth => create_sawteeth(rng, self, verbose).dynamic(),
+            Pattern::Voronoi => create_voronoi(rng, self, verbose).dynamic(),
+            Pattern::Contours => create_contours(rng, self, verbose).dynamic(),
+            Pattern::FlowField => create_flow_field(rng, self, verbose).dynamic(),
+            Pattern::Fractal => create_fractal(rng, self, verbose).dynamic(),
+            Pattern::LinearGradient => {
+                create_gradient(rng, self, GradientShape::Linear, verbose).dynamic()
+            }
+            Pattern::RadialGradient => {
+                create_gradient(rng, self, GradientShape::Radial, verbose).dynamic()
+            }
+            Pattern::Automaton => create_automaton(rng, self, tiles, verbose).dynamic(),
         }
     }
 
     /// Match tiling to function that generates it
-    pub fn make_tiling(&self, rng: &mut ThreadRng) -> Vec<(Pos, Path)> {
+    pub fn make_tiling(&self, rng: &mut StdRng) -> Vec<(Pos, Path)> {
         match self.tiling {
             Tiling::Hexagons => tile_hexagons(&self.frame, self.size_tiling, rng.gen_range(0, 360)),
             Tiling::Triangles => {
@@ -112,38 +250,94 @@ impl SceneCfg {
                 };
                 ptiler(&self.frame, self.size_tiling, rng.gen_range(0, 360))
             }
+            Tiling::Squares => tile_squares(&self.frame, self.size_tiling, rng.gen_range(0, 360)),
+            Tiling::Bricks => tile_bricks(
+                &self.frame,
+                self.size_tiling,
+                self.ratio_bricks,
+                self.offset_bricks,
+                rng.gen_range(0, 360),
+            ),
+            Tiling::Herringbone => {
+                tile_herringbone(&self.frame, self.size_tiling, rng.gen_range(0, 360))
+            }
+            Tiling::Basketweave => {
+                tile_basketweave(&self.frame, self.size_tiling, rng.gen_range(0, 360))
+            }
+            Tiling::OctagonsAndSquares => {
+                tile_octagons_squares(&self.frame, self.size_tiling, rng.gen_range(0, 360))
+            }
+            Tiling::DodecagonsHexagonsAndSquares => tile_dodecagons_hexagons_squares(
+                &self.frame,
+                self.size_tiling,
+                rng.gen_range(0, 360),
+            ),
+            Tiling::DodecagonsAndTriangles => {
+                tile_dodecagons_triangles(&self.frame, self.size_tiling, rng.gen_range(0, 360))
+            }
+            Tiling::ElongatedTriangles => {
+                tile_elongated_triangles(&self.frame, self.size_tiling, rng.gen_range(0, 360))
+            }
+            Tiling::SnubSquares => {
+                tile_snub_squares(&self.frame, self.size_tiling, rng.gen_range(0, 360))
+            }
+            Tiling::SnubHexagons => {
+                tile_snub_hexagons(&self.frame, self.size_tiling, rng.gen_range(0, 360))
+            }
         }
     }
 }
 
 /// Available patterns, open to additions
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Pattern {
     FreeCircles,
     FreeTriangles,
+    FreePolygons,
     FreeStripes,
     FreeSpirals,
+    Curves,
     ConcentricCircles,
+    RadialRays,
+    Arcs,
     ParallelStripes,
     CrossedStripes,
     ParallelWaves,
     ParallelSawteeth,
+    Voronoi,
+    Contours,
+    FlowField,
+    Fractal,
+    LinearGradient,
+    RadialGradient,
+    Automaton,
 }
 
 impl Pattern {
     /// Pick a random pattern (fallback if no other pattern choosing method is specified)
-    pub fn choose(rng: &mut ThreadRng) -> Self {
+    pub fn choose(rng: &mut StdRng) -> Self {
         use Pattern::*;
-        *vec![
+        *[
             FreeCircles,
             FreeTriangles,
+            FreePolygons,
             FreeStripes,
             FreeSpirals,
+            Curves,
             ConcentricCircles,
+            RadialRays,
+            Arcs,
             ParallelStripes,
             CrossedStripes,
             ParallelWaves,
             ParallelSawteeth,
+            Voronoi,
+            Contours,
+            FlowField,
+            Fractal,
+            LinearGradient,
+            RadialGradient,
+            Automaton,
         ]
         .choose(rng)
         .unwrap()
@@ -151,7 +345,8 @@ impl Pattern {
 }
 
 ///Available tilings, open to additions
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "name", content = "variant")]
 pub enum Tiling {
     Hexagons,
     Triangles,
@@ -160,13 +355,23 @@ pub enum Tiling {
     Rhombus,
     Delaunay,
     Pentagons(u8),
+    Squares,
+    Bricks,
+    Herringbone,
+    Basketweave,
+    OctagonsAndSquares,
+    DodecagonsHexagonsAndSquares,
+    DodecagonsAndTriangles,
+    ElongatedTriangles,
+    SnubSquares,
+    SnubHexagons,
 }
 
 impl Tiling {
     /// Pick a random tiling (fallback if no other tiling choosing method is specified)
-    pub fn choose(rng: &mut ThreadRng) -> Self {
+    pub fn choose(rng: &mut StdRng) -> Self {
         use Tiling::*;
-        *vec![
+        *[
             Hexagons,
             Triangles,
             HexagonsAndTriangles,
@@ -174,6 +379,16 @@ impl Tiling {
             Rhombus,
             Delaunay,
             Pentagons(0),
+            Squares,
+            Bricks,
+            Herringbone,
+            Basketweave,
+            OctagonsAndSquares,
+            DodecagonsHexagonsAndSquares,
+            DodecagonsAndTriangles,
+            ElongatedTriangles,
+            SnubSquares,
+            SnubHexagons,
         ]
         .choose(rng)
         .unwrap()
@@ -1,80 +1,221 @@
-use rand::{rngs::ThreadRng, Rng};
+use rand::{rngs::StdRng, Rng};
+use serde_derive::{Deserialize, Serialize};
+use std::sync::OnceLock;
 
-#[derive(Clone)]
-pub struct Chooser<T: Clone>(usize, Vec<(T, usize)>);
+/// Weighted random selection among items.
+/// Items with a weight of zero are kept but never chosen.
+/// Sampling uses the alias method, which takes constant time regardless of the number of items.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "Vec<Weighted<T>>", into = "Vec<Weighted<T>>")]
+pub struct Chooser<T: Clone> {
+    items: Vec<(T, f64)>,
+    /// One slot per enabled item: (probability of keeping the slot's item, item, alias item),
+    /// built on the first pick after the items change
+    table: OnceLock<Vec<(f64, usize, usize)>>,
+}
 
-impl<T: Clone> Chooser<T> {
+/// Serialized representation of an item of a `Chooser`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Weighted<T> {
+    pub weight: f64,
+    pub item: T,
+}
+
+impl<T: Clone> Default for Chooser<T> {
     /// Empty Chooser
-    pub fn default() -> Self {
-        Self(0, Vec::new())
+    fn default() -> Self {
+        Self {
+            items: Vec::new(),
+            table: OnceLock::new(),
+        }
     }
+}
 
+impl<T: Clone> Chooser<T> {
     /// Create Chooser from weighted items
-    pub fn new(mut v: Vec<(T, usize)>) -> Self {
-        let mut sum = 0;
-        for (_, w) in &mut v {
-            sum += *w;
-            *w = sum;
-        }
-        if !v.is_empty() {
-            Self(sum, v)
-        } else {
-            Self::default()
-        }
+    pub fn new(v: Vec<(T, f64)>) -> Self {
+        let mut c = Self::default();
+        c.append(v);
+        c
     }
 
     /// Pick a random item (weighted)
-    pub fn choose(&self, rng: &mut ThreadRng) -> Option<T> {
-        if self.1.is_empty() {
+    pub fn choose(&self, rng: &mut StdRng) -> Option<T> {
+        let table = self.table.get_or_init(|| alias_table(&self.items));
+        if table.is_empty() {
             None
         } else {
-            let choice = rng.gen_range(0, self.0);
-            Some(self.dichotomy(choice, 0, self.1.len()))
+            let (keep, item, alias) = table[rng.gen_range(0, table.len())];
+            let idx = if rng.gen::<f64>() < keep { item } else { alias };
+            Some(self.items[idx].0.clone())
         }
     }
 
-    fn dichotomy(&self, target: usize, inf: usize, sup: usize) -> T {
-        if inf == sup {
-            self.1[inf].0.clone()
-        } else if inf + 1 == sup {
-            if self.1[inf].1 < target {
-                self.1[inf + 1].0.clone()
-            } else {
-                self.1[inf].0.clone()
-            }
-        } else {
-            let mid = (sup + inf) / 2;
-            if self.1[mid].1 > target {
-                self.dichotomy(target, inf, mid)
-            } else {
-                self.dichotomy(target, mid, sup)
-            }
-        }
+    /// Pick up to n different items, with more weight making an item more likely to be picked first
+    pub fn choose_n_distinct(&self, n: usize, rng: &mut StdRng) -> Vec<T> {
+        // Efraimidis-Spirakis: keep the n largest values of u^(1/w)
+        let mut keys = self
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, w))| *w > 0.)
+            .map(|(i, (_, w))| (rng.gen::<f64>().powf(1. / w), i))
+            .collect::<Vec<_>>();
+        keys.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+        keys.into_iter()
+            .take(n)
+            .map(|(_, i)| self.items[i].0.clone())
+            .collect()
     }
 
     /// Get items with their weights as a copy
-    pub fn extract(&self) -> Vec<(T, usize)> {
-        let mut cpy = self.1.clone();
-        let n = cpy.len();
-        for i in 1..n {
-            cpy[n - i].1 -= cpy[n - i - 1].1;
-        }
-        cpy
+    pub fn extract(&self) -> Vec<(T, f64)> {
+        self.items.clone()
     }
 
-    /// Add new item
-    pub fn push(&mut self, item: T, w: usize) {
-        if w == 0 {
-            panic!("In call to Chooser::push, 0 is not a valid weight");
-        }
-        self.0 += w;
-        self.1.push((item, self.0));
+    /// Add new item, a weight of 0 means that the item is disabled
+    pub fn push(&mut self, item: T, w: f64) {
+        self.items.push((item, sanitize(w)));
+        self.table = OnceLock::new();
     }
 
     /// Add vector of new items
-    pub fn append(&mut self, items: Vec<(T, usize)>) {
+    pub fn append(&mut self, items: Vec<(T, f64)>) {
         for (item, w) in items {
-            self.push(item, w);
+            self.items.push((item, sanitize(w)));
+        }
+        self.table = OnceLock::new();
+    }
+
+    /// Remove the idx'th item that was added
+    pub fn remove(&mut self, idx: usize) -> (T, f64) {
+        let removed = self.items.remove(idx);
+        self.table = OnceLock::new();
+        removed
+    }
+
+    /// Change the weight of the idx'th item that was added
+    pub fn set_weight(&mut self, idx: usize, w: f64) {
+        self.items[idx].1 = sanitize(w);
+        self.table = OnceLock::new();
+    }
+
+    /// Number of items, including disabled ones
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+/// Vose's construction of the alias table
+fn alias_table<T>(items: &[(T, f64)]) -> Vec<(f64, usize, usize)> {
+    let enabled = items
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, w))| *w > 0.)
+        .map(|(i, (_, w))| (i, *w))
+        .collect::<Vec<_>>();
+    let n = enabled.len();
+    let total = enabled.iter().map(|(_, w)| w).sum::<f64>();
+    let mut scaled = enabled
+        .iter()
+        .map(|(_, w)| w * n as f64 / total)
+        .collect::<Vec<_>>();
+    let (mut small, mut large): (Vec<_>, Vec<_>) = (0..n).partition(|&k| scaled[k] < 1.);
+    let mut table = enabled.iter().map(|&(i, _)| (1., i, i)).collect::<Vec<_>>();
+    while let (Some(s), Some(&l)) = (small.pop(), large.last()) {
+        table[s].0 = scaled[s];
+        table[s].2 = enabled[l].0;
+        scaled[l] += scaled[s] - 1.;
+        if scaled[l] < 1. {
+            large.pop();
+            small.push(l);
+        }
+    }
+    table
+}
+
+/// Negative, infinite or NaN weights disable the item
+fn sanitize(w: f64) -> f64 {
+    if w.is_finite() && w > 0. {
+        w
+    } else {
+        0.
+    }
+}
+
+impl<T: Clone> From<Vec<Weighted<T>>> for Chooser<T> {
+    fn from(v: Vec<Weighted<T>>) -> Self {
+        Self::new(v.into_iter().map(|w| (w.item, w.weight)).collect())
+    }
+}
+
+impl<T: Clone> From<Chooser<T>> for Vec<Weighted<T>> {
+    fn from(c: Chooser<T>) -> Self {
+        c.items
+            .into_iter()
+            .map(|(item, weight)| Weighted { item, weight })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn remove_forgets_the_item() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut c = Chooser::new(vec![('a', 1.), ('b', 1.), ('c', 1.)]);
+        assert_eq!(c.remove(1), ('b', 1.));
+        assert_eq!(c.len(), 2);
+        for _ in 0..1000 {
+            assert_ne!(c.choose(&mut rng), Some('b'));
+        }
+    }
+
+    #[test]
+    fn zero_weight_is_never_chosen() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut c = Chooser::new(vec![('a', 1.), ('b', 5.)]);
+        assert!(c.choose(&mut rng).is_some());
+        c.set_weight(1, 0.);
+        for _ in 0..1000 {
+            assert_eq!(c.choose(&mut rng), Some('a'));
+        }
+        c.set_weight(0, 0.);
+        assert_eq!(c.choose(&mut rng), None);
+    }
+
+    #[test]
+    fn invalid_weights_are_disabled() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let c = Chooser::new(vec![
+            ('a', 1.),
+            ('b', f64::INFINITY),
+            ('c', f64::NAN),
+            ('d', -1.),
+        ]);
+        for _ in 0..1000 {
+            assert_eq!(c.choose(&mut rng), Some('a'));
+        }
+    }
+
+    #[test]
+    fn distinct_picks_have_no_duplicates() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let c = Chooser::new(vec![('a', 1.), ('b', 2.), ('c', 0.), ('d', 3.)]);
+        for n in 0..6 {
+            let mut picked = c.choose_n_distinct(n, &mut rng);
+            assert_eq!(picked.len(), n.min(3));
+            assert!(!picked.contains(&'c'));
+            picked.sort_unstable();
+            picked.dedup();
+            assert_eq!(picked.len(), n.min(3));
         }
     }
 }
@@ -1,8 +1,11 @@
-use rand::{rngs::ThreadRng, Rng};
+use crate::noise::Noise;
+use crate::prelude::*;
+use rand::{rngs::StdRng, Rng};
+use serde_derive::{Deserialize, Serialize};
 use std::convert::TryInto;
 use std::fmt;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Color(pub usize, pub usize, pub usize);
 
 impl Color {
@@ -15,22 +18,37 @@ impl Color {
     }
 
     /// Random noise
-    pub fn variate(mut self, rng: &mut ThreadRng, amount: usize) -> Self {
+    pub fn variate(mut self, rng: &mut StdRng, amount: usize) -> Self {
         if amount > 0 {
             let amount = amount as isize;
-            self.0 = (self.0 as isize + rng.gen_range(-amount as isize, amount as isize))
+            self.0 = (self.0 as isize + rng.gen_range(-amount, amount))
                 .try_into()
                 .unwrap_or(0);
-            self.1 = (self.1 as isize + rng.gen_range(-amount as isize, amount as isize))
+            self.1 = (self.1 as isize + rng.gen_range(-amount, amount))
                 .try_into()
                 .unwrap_or(0);
-            self.2 = (self.2 as isize + rng.gen_range(-amount as isize, amount as isize))
+            self.2 = (self.2 as isize + rng.gen_range(-amount, amount))
                 .try_into()
                 .unwrap_or(0);
         }
         self
     }
 
+    /// Coherent noise: nearby positions get similar deviations
+    pub fn variate_field(mut self, noise: &Noise, p: Pos, amount: usize) -> Self {
+        if amount > 0 {
+            let shift = |c: usize, i| {
+                (c as isize + (noise.channel(i).sample(p) * amount as f64) as isize)
+                    .try_into()
+                    .unwrap_or(0)
+            };
+            self.0 = shift(self.0, 0);
+            self.1 = shift(self.1, 1);
+            self.2 = shift(self.2, 2);
+        }
+        self
+    }
+
     /// Weighted mix with other color
     pub fn meanpoint(mut self, th: Self, distance: usize) -> Self {
         self.0 = (self.0 * distance + th.0 * (100 - distance)) / 100;
@@ -40,13 +58,83 @@ impl Color {
     }
 
     /// Generate color
-    pub fn random(rng: &mut ThreadRng) -> Self {
+    pub fn random(rng: &mut StdRng) -> Self {
         Self(
             rng.gen_range(0, 255),
             rng.gen_range(0, 255),
             rng.gen_range(0, 255),
         )
     }
+    /// Parse `#RRGGBB`
+    pub fn from_hex(s: &str) -> Option<Self> {
+        if s.len() == 7 && &s[0..1] == "#" {
+            let r = usize::from_str_radix(&s[1..3], 16);
+            let g = usize::from_str_radix(&s[3..5], 16);
+            let b = usize::from_str_radix(&s[5..7], 16);
+            match (r, g, b) {
+                (Ok(r), Ok(g), Ok(b)) => Some(Color(r, g, b)),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Floating point channels within [0; 255]
+    pub fn to_f64(self) -> (f64, f64, f64) {
+        let c = self.validate();
+        (c.0 as f64, c.1 as f64, c.2 as f64)
+    }
+
+    /// Round and clamp floating point channels
+    pub fn from_f64((r, g, b): (f64, f64, f64)) -> Self {
+        let conv = |x: f64| x.round().clamp(0., 255.) as usize;
+        Self(conv(r), conv(g), conv(b))
+    }
+
+    /// Perceived brightness within [0; 255]
+    pub fn luma(self) -> f64 {
+        let (r, g, b) = self.to_f64();
+        0.299 * r + 0.587 * g + 0.114 * b
+    }
+
+    /// Hue (degrees), saturation and lightness (both within [0; 1])
+    pub fn to_hsl(self) -> (f64, f64, f64) {
+        let (r, g, b) = self.to_f64();
+        let (r, g, b) = (r / 255., g / 255., b / 255.);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let l = (max + min) / 2.;
+        let d = max - min;
+        if d == 0. {
+            return (0., 0., l);
+        }
+        let s = d / (1. - (2. * l - 1.).abs());
+        let h = if max == r {
+            ((g - b) / d).rem_euclid(6.)
+        } else if max == g {
+            (b - r) / d + 2.
+        } else {
+            (r - g) / d + 4.
+        };
+        (h * 60., s, l)
+    }
+
+    pub fn from_hsl((h, s, l): (f64, f64, f64)) -> Self {
+        let c = (1. - (2. * l - 1.).abs()) * s;
+        let h = h.rem_euclid(360.) / 60.;
+        let x = c * (1. - (h.rem_euclid(2.) - 1.).abs());
+        let (r, g, b) = match h as usize {
+            0 => (c, x, 0.),
+            1 => (x, c, 0.),
+            2 => (0., c, x),
+            3 => (0., x, c),
+            4 => (x, 0., c),
+            _ => (c, 0., x),
+        };
+        let m = l - c / 2.;
+        Self::from_f64(((r + m) * 255., (g + m) * 255., (b + m) * 255.))
+    }
 }
 
 /// SVG color format: `rgb(<r>,<g>,<b>)`
@@ -0,0 +1,209 @@
+use crate::prelude::*;
+use crate::scene::Scene;
+use crate::svg::{Data, Path};
+use rand::rngs::StdRng;
+
+/// How a tile that overlaps several items gets its color
+#[derive(Debug, Clone, Copy)]
+pub enum CoverageMode {
+    /// Color of the item that covers most of the tile
+    Majority,
+    /// Mix of the colors of all items, weighted by how much of the tile they cover
+    Blend,
+}
+
+impl CoverageMode {
+    pub fn from(s: &str) -> Option<Self> {
+        match s {
+            "majority" => Some(CoverageMode::Majority),
+            "blend" => Some(CoverageMode::Blend),
+            _ => None,
+        }
+    }
+}
+
+/// Color tiles according to the area they share with each item
+/// instead of according to their center only
+#[derive(Debug, Clone, Copy)]
+pub struct Coverage {
+    pub mode: CoverageMode,
+    /// Number of points evaluated inside each tile
+    pub samples: usize,
+    /// Levels of subdivision used to cut tiles along the edges of items, 0 to never cut tiles
+    pub split: usize,
+}
+
+/// Part of a tile covered by a single item
+struct Group {
+    key: Option<usize>,
+    count: usize,
+    /// A point of the tile where the item is visible
+    at: Pos,
+}
+
+impl Coverage {
+    /// Color of a tile and slope of the item that covers it.
+    /// Also returns the pieces of the tile that should be painted over it
+    /// with their own color when tiles are split.
+    pub fn color(
+        &self,
+        scene: &Scene,
+        center: Pos,
+        path: &Path,
+        rng: &mut StdRng,
+    ) -> (Color, Pos, Vec<(Path, Color, Pos)>) {
+        let polygon = &path.data.0;
+        let mut groups: Vec<Group> = Vec::new();
+        let add = |groups: &mut Vec<Group>, p: Pos| {
+            let key = scene.hit(p);
+            match groups.iter_mut().find(|g| g.key == key) {
+                Some(g) => g.count += 1,
+                None => groups.push(Group {
+                    key,
+                    count: 1,
+                    at: p,
+                }),
+            }
+        };
+        for p in sample_points(polygon, self.samples) {
+            add(&mut groups, p);
+        }
+        if groups.is_empty() {
+            add(&mut groups, center);
+        }
+        let main = (0..groups.len())
+            .max_by_key(|&i| (groups[i].count, std::cmp::Reverse(i)))
+            .unwrap();
+        let (color, slope) = match self.mode {
+            CoverageMode::Blend if self.split == 0 => {
+                let total = groups.iter().map(|g| g.count).sum::<usize>() as f64;
+                let mut mix = (0., 0., 0.);
+                let mut slope = Pos::zero();
+                for (i, g) in groups.iter().enumerate() {
+                    let (c, s) = scene.color_relief(g.at, rng);
+                    if i == main {
+                        slope = s;
+                    }
+                    let (r, gr, b) = c.to_f64();
+                    let w = g.count as f64 / total;
+                    mix = (mix.0 + r * w, mix.1 + gr * w, mix.2 + b * w);
+                }
+                (Color::from_f64(mix), slope)
+            }
+            // Cut tiles show the other items in their pieces
+            CoverageMode::Majority | CoverageMode::Blend => {
+                scene.color_relief(groups[main].at, rng)
+            }
+        };
+        let pieces = if self.split > 0 && groups.len() > 1 {
+            self.pieces(scene, center, polygon, groups[main].key, rng)
+        } else {
+            Vec::new()
+        };
+        (color, slope, pieces)
+    }
+
+    /// Triangles of the tile that are not covered by the main item,
+    /// found by recursively subdividing the triangles that straddle an edge
+    fn pieces(
+        &self,
+        scene: &Scene,
+        center: Pos,
+        polygon: &[Pos],
+        main: Option<usize>,
+        rng: &mut StdRng,
+    ) -> Vec<(Path, Color, Pos)> {
+        let mut leaves = Vec::new();
+        for i in 0..polygon.len() {
+            let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+            subdivide(scene, [center, a, b], self.split, &mut leaves);
+        }
+        let mut pieces = Vec::new();
+        let mut colors: Vec<(Option<usize>, Color)> = Vec::new();
+        for (tri, key) in leaves {
+            if key == main {
+                continue;
+            }
+            let p = centroid(&tri);
+            let color = match colors.iter().find(|(k, _)| *k == key) {
+                Some(&(_, c)) => c,
+                None => {
+                    let c = scene.color_relief(p, rng).0;
+                    colors.push((key, c));
+                    c
+                }
+            };
+            let path = Path::new(Data::new(tri[0]).with_line_to(tri[1]).with_line_to(tri[2]));
+            let slope = key.map(|i| scene.items[i].relief(p)).unwrap_or(Pos::zero());
+            pieces.push((path, color, slope));
+        }
+        pieces
+    }
+}
+
+fn centroid(tri: &[Pos; 3]) -> Pos {
+    (tri[0] + tri[1] + tri[2]) * (1. / 3.)
+}
+
+/// Split a triangle in four until it is either covered by a single item or small enough
+fn subdivide(
+    scene: &Scene,
+    tri: [Pos; 3],
+    depth: usize,
+    leaves: &mut Vec<([Pos; 3], Option<usize>)>,
+) {
+    let key = scene.hit(centroid(&tri));
+    let uniform = tri
+        .iter()
+        .all(|&v| scene.hit(v * 0.99 + centroid(&tri) * 0.01) == key);
+    if depth == 0 || uniform {
+        leaves.push((tri, key));
+    } else {
+        let [a, b, c] = tri;
+        let (ab, bc, ca) = ((a + b) * 0.5, (b + c) * 0.5, (c + a) * 0.5);
+        for t in [[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]] {
+            subdivide(scene, t, depth - 1, leaves);
+        }
+    }
+}
+
+/// Points spread evenly over the inside of a polygon
+fn sample_points(polygon: &[Pos], n: usize) -> Vec<Pos> {
+    if polygon.is_empty() {
+        return Vec::new();
+    }
+    let mut lo = polygon[0];
+    let mut hi = polygon[0];
+    for &Pos(x, y) in polygon {
+        lo = Pos(lo.0.min(x), lo.1.min(y));
+        hi = Pos(hi.0.max(x), hi.1.max(y));
+    }
+    let k = (n as f64).sqrt().ceil().max(1.) as usize;
+    let mut pts = Vec::new();
+    for i in 0..k {
+        for j in 0..k {
+            let p = Pos(
+                lo.0 + (hi.0 - lo.0) * (i as f64 + 0.5) / k as f64,
+                lo.1 + (hi.1 - lo.1) * (j as f64 + 0.5) / k as f64,
+            );
+            if inside(polygon, p) {
+                pts.push(p);
+            }
+        }
+    }
+    pts
+}
+
+/// Even-odd rule
+fn inside(polygon: &[Pos], p: Pos) -> bool {
+    let mut result = false;
+    let mut j = polygon.len() - 1;
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[j]);
+        if (a.1 > p.1) != (b.1 > p.1) && p.0 < (b.0 - a.0) * (p.1 - a.1) / (b.1 - a.1) + a.0 {
+            result = !result;
+        }
+        j = i;
+    }
+    result
+}
@@ -0,0 +1,360 @@
+use crate::prelude::*;
+use crate::scene::*;
+use rand::{rngs::StdRng, Rng};
+use std::sync::Arc;
+
+/// Linear part of an affine transform, as rows of a 2x2 matrix
+#[derive(Debug, Clone, Copy)]
+pub struct Matrix(pub [[f64; 2]; 2]);
+
+impl Matrix {
+    pub fn identity() -> Self {
+        Self([[1., 0.], [0., 1.]])
+    }
+
+    pub fn rotation(a: isize) -> Self {
+        let (s, c) = radians(a).sin_cos();
+        Self([[c, -s], [s, c]])
+    }
+
+    pub fn scale(sx: f64, sy: f64) -> Self {
+        Self([[sx, 0.], [0., sy]])
+    }
+
+    /// Horizontal shear
+    pub fn skew(k: f64) -> Self {
+        Self([[1., k], [0., 1.]])
+    }
+
+    pub fn then(self, other: Self) -> Self {
+        let (a, b) = (other.0, self.0);
+        Self([
+            [
+                a[0][0] * b[0][0] + a[0][1] * b[1][0],
+                a[0][0] * b[0][1] + a[0][1] * b[1][1],
+            ],
+            [
+                a[1][0] * b[0][0] + a[1][1] * b[1][0],
+                a[1][0] * b[0][1] + a[1][1] * b[1][1],
+            ],
+        ])
+    }
+
+    pub fn apply(&self, Pos(x, y): Pos) -> Pos {
+        let m = self.0;
+        Pos(m[0][0] * x + m[0][1] * y, m[1][0] * x + m[1][1] * y)
+    }
+
+    pub fn transpose(self) -> Self {
+        let m = self.0;
+        Self([[m[0][0], m[1][0]], [m[0][1], m[1][1]]])
+    }
+
+    /// None if the transform flattens the plane
+    pub fn inverse(self) -> Option<Self> {
+        let m = self.0;
+        let det = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+        if det.abs() < 1e-9 {
+            None
+        } else {
+            Some(Self([
+                [m[1][1] / det, -m[0][1] / det],
+                [-m[1][0] / det, m[0][0] / det],
+            ]))
+        }
+    }
+}
+
+/// Item deformed by an affine transform around a fixed point
+pub struct Transformed {
+    pub inner: Arc<dyn Contains>,
+    pub origin: Pos,
+    pub matrix: Matrix,
+    /// Maps points of the scene back to points of the inner item
+    pub inverse: Matrix,
+}
+
+impl Transformed {
+    /// A transform that flattens the plane leaves the item unchanged
+    pub fn new(inner: Arc<dyn Contains>, origin: Pos, matrix: Matrix) -> Self {
+        let (matrix, inverse) = match matrix.inverse() {
+            Some(inverse) => (matrix, inverse),
+            None => (Matrix::identity(), Matrix::identity()),
+        };
+        Self {
+            inner,
+            origin,
+            matrix,
+            inverse,
+        }
+    }
+
+    /// Factor by which the transform scales areas
+    fn det(&self) -> f64 {
+        let m = self.matrix.0;
+        m[0][0] * m[1][1] - m[0][1] * m[1][0]
+    }
+
+    fn local(&self, p: Pos) -> Pos {
+        self.origin + self.inverse.apply(p - self.origin)
+    }
+}
+
+impl Contains for Transformed {
+    fn hit(&self, p: Pos) -> bool {
+        self.inner.hit(self.local(p))
+    }
+
+    fn color(&self) -> &ColorItem {
+        self.inner.color()
+    }
+
+    fn contains(&self, p: Pos, rng: &mut StdRng) -> Option<Color> {
+        self.inner.contains(self.local(p), rng)
+    }
+
+    fn relief(&self, p: Pos) -> Pos {
+        self.inverse
+            .transpose()
+            .apply(self.inner.relief(self.local(p)))
+    }
+
+    fn bbox(&self) -> Option<(Pos, Pos)> {
+        let (lo, hi) = self.inner.bbox()?;
+        let corners = [lo, Pos(lo.0, hi.1), hi, Pos(hi.0, lo.1)]
+            .iter()
+            .map(|&c| self.origin + self.matrix.apply(c - self.origin))
+            .collect::<Vec<_>>();
+        Some(hull(&corners))
+    }
+
+    /// Exact for rotations and uniform scaling, approximate otherwise
+    fn edge(&self, p: Pos) -> Option<f64> {
+        Some(self.inner.edge(self.local(p))? * self.det().abs().sqrt())
+    }
+}
+
+/// Smallest box that contains all points
+fn hull(pts: &[Pos]) -> (Pos, Pos) {
+    let mut lo = Pos(f64::INFINITY, f64::INFINITY);
+    let mut hi = Pos(f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for &Pos(x, y) in pts {
+        lo = Pos(lo.0.min(x), lo.1.min(y));
+        hi = Pos(hi.0.max(x), hi.1.max(y));
+    }
+    (lo, hi)
+}
+
+/// Boolean operations on the areas of two items
+#[derive(Debug, Clone, Copy)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference,
+    Xor,
+}
+
+impl CsgOp {
+    pub fn from(s: &str) -> Option<Self> {
+        match s {
+            "union" => Some(CsgOp::Union),
+            "intersection" => Some(CsgOp::Intersection),
+            "difference" => Some(CsgOp::Difference),
+            "xor" => Some(CsgOp::Xor),
+            _ => None,
+        }
+    }
+}
+
+/// Two items combined into one.
+/// Where both are visible, the first one gives its color.
+pub struct Combined {
+    pub op: CsgOp,
+    pub a: Arc<dyn Contains>,
+    pub b: Arc<dyn Contains>,
+}
+
+impl Combined {
+    /// The operand that is visible at p, if any
+    fn visible(&self, p: Pos) -> Option<&Arc<dyn Contains>> {
+        let (ha, hb) = (self.a.hit(p), self.b.hit(p));
+        let show_a = match self.op {
+            CsgOp::Union => ha,
+            CsgOp::Intersection => ha && hb,
+            CsgOp::Difference | CsgOp::Xor => ha && !hb,
+        };
+        let show_b = match self.op {
+            CsgOp::Union => hb,
+            CsgOp::Xor => hb && !ha,
+            CsgOp::Intersection | CsgOp::Difference => false,
+        };
+        if show_a {
+            Some(&self.a)
+        } else if show_b {
+            Some(&self.b)
+        } else {
+            None
+        }
+    }
+}
+
+impl Contains for Combined {
+    fn hit(&self, p: Pos) -> bool {
+        self.visible(p).is_some()
+    }
+
+    fn color(&self) -> &ColorItem {
+        self.a.color()
+    }
+
+    fn contains(&self, p: Pos, rng: &mut StdRng) -> Option<Color> {
+        self.visible(p).and_then(|x| x.contains(p, rng))
+    }
+
+    fn relief(&self, p: Pos) -> Pos {
+        self.visible(p).map(|x| x.relief(p)).unwrap_or(Pos::zero())
+    }
+
+    fn bbox(&self) -> Option<(Pos, Pos)> {
+        match self.op {
+            CsgOp::Union | CsgOp::Xor => {
+                let (a, b) = (self.a.bbox()?, self.b.bbox()?);
+                Some(hull(&[a.0, a.1, b.0, b.1]))
+            }
+            CsgOp::Intersection => match (self.a.bbox(), self.b.bbox()) {
+                (Some(a), Some(b)) => Some((
+                    Pos(a.0 .0.max(b.0 .0), a.0 .1.max(b.0 .1)),
+                    Pos(a.1 .0.min(b.1 .0), a.1 .1.min(b.1 .1)),
+                )),
+                (a, b) => a.or(b),
+            },
+            CsgOp::Difference => self.a.bbox(),
+        }
+    }
+
+    fn edge(&self, p: Pos) -> Option<f64> {
+        let (a, b) = (self.a.edge(p)?, self.b.edge(p)?);
+        Some(match self.op {
+            CsgOp::Union => a.max(b),
+            CsgOp::Intersection => a.min(b),
+            CsgOp::Difference => a.min(-b),
+            CsgOp::Xor => a.min(-b).max(b.min(-a)),
+        })
+    }
+}
+
+/// What each item is combined with
+#[derive(Debug, Clone, Copy)]
+pub enum Operand {
+    /// A copy of the item itself, scaled around its center
+    Inner,
+    /// A random disc
+    Disc,
+    /// The item that comes after it, which is used up:
+    /// items are combined two by two
+    Next,
+}
+
+impl Operand {
+    pub fn from(s: &str) -> Option<Self> {
+        match s {
+            "inner" => Some(Operand::Inner),
+            "disc" => Some(Operand::Disc),
+            "next" => Some(Operand::Next),
+            _ => None,
+        }
+    }
+}
+
+/// Boolean combination applied to every item of a scene, layers included.
+/// Patterns cannot have settings of their own.
+#[derive(Debug, Clone, Copy)]
+pub struct Csg {
+    pub op: CsgOp,
+    pub operand: Operand,
+    /// Size of the inner copy relative to the item, or of the disc relative to the frame
+    pub ratio: f64,
+}
+
+/// Point around which an item is transformed
+fn anchor(item: &Arc<dyn Contains>, f: &Frame) -> Pos {
+    match item.bbox() {
+        Some((lo, hi)) => (lo + hi) * 0.5,
+        None => f.center(),
+    }
+}
+
+impl Csg {
+    pub fn apply(
+        &self,
+        items: Vec<Arc<dyn Contains>>,
+        f: &Frame,
+        rng: &mut StdRng,
+    ) -> Vec<Arc<dyn Contains>> {
+        let combine = |a, b| Arc::new(Combined { op: self.op, a, b }) as Arc<dyn Contains>;
+        match self.operand {
+            Operand::Inner => items
+                .into_iter()
+                .map(|a| {
+                    let inner = Transformed::new(
+                        a.clone(),
+                        anchor(&a, f),
+                        Matrix::scale(self.ratio, self.ratio),
+                    );
+                    combine(a, Arc::new(inner))
+                })
+                .collect(),
+            Operand::Disc => items
+                .into_iter()
+                .map(|a| {
+                    let disc = Disc {
+                        center: Pos::random(f, rng),
+                        radius: self.ratio * f.w.min(f.h) as f64 / 2.,
+                        color: a.color().clone(),
+                    };
+                    combine(a, Arc::new(disc))
+                })
+                .collect(),
+            Operand::Next => items
+                .chunks(2)
+                .map(|pair| match pair {
+                    [a, b] => combine(a.clone(), b.clone()),
+                    _ => pair[0].clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Affine transform applied to every item of a scene, layers included,
+/// each around its own center. Patterns cannot have settings of their own.
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    pub matrix: Matrix,
+    /// Additional random rotation of each item, in degrees
+    pub jitter: isize,
+}
+
+impl Transform {
+    pub fn apply(
+        &self,
+        items: Vec<Arc<dyn Contains>>,
+        f: &Frame,
+        rng: &mut StdRng,
+    ) -> Vec<Arc<dyn Contains>> {
+        items
+            .into_iter()
+            .map(|item| {
+                let matrix = if self.jitter > 0 {
+                    self.matrix.then(Matrix::rotation(
+                        rng.gen_range(-self.jitter, self.jitter + 1),
+                    ))
+                } else {
+                    self.matrix
+                };
+                let origin = anchor(&item, f);
+                Arc::new(Transformed::new(item, origin, matrix)) as Arc<dyn Contains>
+            })
+            .collect()
+    }
+}
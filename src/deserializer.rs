@@ -1,11 +1,21 @@
-use crate::cfg::SceneCfg;
+use crate::blend::{Blend, BlendMode};
+use crate::cfg::{Layer, PatternParams, SceneCfg};
+use crate::coverage::{Coverage, CoverageMode};
+use crate::csg::{Csg, CsgOp, Matrix, Operand, Transform};
+use crate::feather::{Feather, FeatherMode};
+use crate::filter::Filter;
+use crate::lighting::Lighting;
+use crate::noise::{Noise, NoiseKind};
+use crate::paint::{Background, CurveFamily, FractalKind, RayCenter, Rule, SizeDistribution};
 use crate::prelude::*;
-use rand::{rngs::ThreadRng, seq::SliceRandom};
-use serde_derive::Deserialize;
+use crate::salt::{Cluster, ClusterMode};
+use crate::scene::{GradientShape, Metric};
+use rand::{rngs::StdRng, seq::SliceRandom};
+use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 use toml::{map::Map, Value};
 
-const BASE_WEIGHT: usize = 10;
+const BASE_WEIGHT: f64 = 10.;
 
 /// All config information
 #[derive(Deserialize, Default, Debug)]
@@ -16,7 +26,17 @@ pub struct MetaConfig {
     pub themes: Option<ConfigThemes>,
     pub shapes: Option<ConfigShapes>,
     pub data: Option<ConfigData>,
+    pub noise: Option<ConfigNoise>,
+    pub lighting: Option<ConfigLighting>,
+    pub filter: Option<ConfigFilter>,
+    pub blend: Option<ConfigBlend>,
+    pub coverage: Option<ConfigCoverage>,
+    pub csg: Option<ConfigCsg>,
+    pub transform: Option<ConfigTransform>,
+    pub feather: Option<ConfigFeather>,
+    pub background: Option<ConfigBackground>,
     pub entry: Option<Vec<ConfigEntry>>,
+    pub layer: Option<Vec<ConfigLayer>>,
 }
 
 /// Global options
@@ -49,6 +69,104 @@ pub struct ConfigLines {
     pub squ_and_tri_color: Option<String>,
     pub pen_width: Option<f64>,
     pub pen_color: Option<String>,
+    pub squ_width: Option<f64>,
+    pub squ_color: Option<String>,
+    pub bri_width: Option<f64>,
+    pub bri_color: Option<String>,
+    pub her_width: Option<f64>,
+    pub her_color: Option<String>,
+    pub bas_width: Option<f64>,
+    pub bas_color: Option<String>,
+    pub oct_and_squ_width: Option<f64>,
+    pub oct_and_squ_color: Option<String>,
+    pub dod_hex_and_squ_width: Option<f64>,
+    pub dod_hex_and_squ_color: Option<String>,
+    pub dod_and_tri_width: Option<f64>,
+    pub dod_and_tri_color: Option<String>,
+    pub elo_tri_width: Option<f64>,
+    pub elo_tri_color: Option<String>,
+    pub snub_squ_width: Option<f64>,
+    pub snub_squ_color: Option<String>,
+    pub snub_hex_width: Option<f64>,
+    pub snub_hex_color: Option<String>,
+}
+
+/// Coherent color noise
+#[derive(Deserialize, Default, Debug)]
+pub struct ConfigNoise {
+    pub kind: Option<String>,
+    pub scale: Option<f64>,
+    pub octaves: Option<usize>,
+}
+
+/// Directional light source
+#[derive(Deserialize, Default, Debug)]
+pub struct ConfigLighting {
+    pub angle: Option<isize>,
+    pub strength: Option<f64>,
+}
+
+/// Color adjustments
+#[derive(Deserialize, Default, Debug, Clone)]
+pub struct ConfigFilter {
+    pub temperature: Option<f64>,
+    pub hue: Option<f64>,
+    pub saturation: Option<f64>,
+    pub brightness: Option<f64>,
+    pub contrast: Option<f64>,
+    pub grayscale: Option<bool>,
+    pub sepia: Option<bool>,
+    pub duotone: Option<Vec<String>>,
+}
+
+/// Coloring of tiles that overlap several items
+#[derive(Deserialize, Default, Debug)]
+pub struct ConfigCoverage {
+    pub mode: Option<String>,
+    pub samples: Option<usize>,
+    pub split: Option<usize>,
+}
+
+/// Boolean combination of every item of the scene
+#[derive(Deserialize, Default, Debug)]
+pub struct ConfigCsg {
+    pub op: Option<String>,
+    pub operand: Option<String>,
+    pub ratio: Option<f64>,
+}
+
+/// Deformation of every item of the scene
+#[derive(Deserialize, Default, Debug)]
+pub struct ConfigTransform {
+    pub rotate: Option<isize>,
+    pub scale: Option<Vec<f64>>,
+    pub skew: Option<f64>,
+    pub jitter: Option<isize>,
+}
+
+/// Soft edges of items
+#[derive(Deserialize, Default, Debug)]
+pub struct ConfigFeather {
+    pub mode: Option<String>,
+    pub width: Option<f64>,
+}
+
+/// Gradient behind all items
+#[derive(Deserialize, Default, Debug)]
+pub struct ConfigBackground {
+    pub shape: Option<String>,
+    pub colors: Option<usize>,
+    pub bands: Option<bool>,
+    pub jitter: Option<f64>,
+}
+
+/// Composition of overlapping items
+#[derive(Deserialize, Default, Debug)]
+pub struct ConfigBlend {
+    pub mode: Option<String>,
+    pub opacity: Option<f64>,
+    /// Overrides for specific patterns, keyed by pattern name
+    pub patterns: Option<Map<String, Value>>,
 }
 
 /// Color list
@@ -72,6 +190,15 @@ pub struct ConfigShapes {
     pub list: Map<String, Value>,
 }
 
+/// Weighted shapes of an entry, as they are after resolving references to other shapes
+#[derive(Serialize)]
+struct ResolvedShapes<'a> {
+    patterns: &'a Chooser<Pattern>,
+    tilings: &'a Chooser<Tiling>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    layers: &'a [Chooser<Pattern>],
+}
+
 /// Group together pattern options and tiling options
 #[derive(Deserialize, Default, Debug)]
 pub struct ConfigData {
@@ -88,21 +215,44 @@ pub struct ConfigTilings {
     pub size_squ_and_tri: Option<f64>,
     pub size_rho: Option<f64>,
     pub size_pen: Option<f64>,
+    pub size_squ: Option<f64>,
+    pub size_bri: Option<f64>,
+    pub size_her: Option<f64>,
+    pub size_bas: Option<f64>,
+    pub size_oct_and_squ: Option<f64>,
+    pub size_dod_hex_and_squ: Option<f64>,
+    pub size_dod_and_tri: Option<f64>,
+    pub size_elo_tri: Option<f64>,
+    pub size_snub_squ: Option<f64>,
+    pub size_snub_hex: Option<f64>,
     pub nb_delaunay: Option<usize>,
+    pub ratio_bricks: Option<f64>,
+    pub offset_bricks: Option<f64>,
 }
 
 /// Pattern options
-#[derive(Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
 pub struct ConfigPatterns {
     pub nb_free_circles: Option<usize>,
     pub nb_free_spirals: Option<usize>,
+    pub nb_curves: Option<usize>,
     pub nb_free_stripes: Option<usize>,
     pub nb_crossed_stripes: Option<usize>,
     pub nb_parallel_stripes: Option<usize>,
     pub nb_concentric_circles: Option<usize>,
+    pub nb_radial_rays: Option<usize>,
+    pub nb_arcs: Option<usize>,
     pub nb_free_triangles: Option<usize>,
+    pub nb_free_polygons: Option<usize>,
     pub nb_parallel_waves: Option<usize>,
     pub nb_parallel_sawteeth: Option<usize>,
+    pub nb_voronoi: Option<usize>,
+    pub nb_contours: Option<usize>,
+    pub nb_flow_field: Option<usize>,
+    pub nb_fractal: Option<usize>,
+    pub nb_linear_gradient: Option<usize>,
+    pub nb_radial_gradient: Option<usize>,
+    pub nb_automaton: Option<usize>,
     pub var_parallel_stripes: Option<usize>,
     pub var_crossed_stripes: Option<usize>,
     pub width_spiral: Option<f64>,
@@ -110,6 +260,50 @@ pub struct ConfigPatterns {
     pub width_wave: Option<f64>,
     pub width_sawtooth: Option<f64>,
     pub tightness_spiral: Option<f64>,
+    pub weight_voronoi: Option<f64>,
+    pub metric_voronoi: Option<String>,
+    pub scale_contours: Option<f64>,
+    pub octaves_contours: Option<usize>,
+    pub terrace_contours: Option<bool>,
+    pub sides_polygons: Option<Vec<usize>>,
+    pub star_polygons: Option<f64>,
+    pub rotation_polygons: Option<isize>,
+    pub size_polygons: Option<Vec<f64>>,
+    pub distribution_polygons: Option<String>,
+    pub center_rays: Option<String>,
+    pub jitter_rays: Option<f64>,
+    pub twist_rays: Option<isize>,
+    pub alternate_rays: Option<bool>,
+    pub centers_arcs: Option<usize>,
+    pub gap_arcs: Option<f64>,
+    pub width_arcs: Option<f64>,
+    pub families_curves: Option<Vec<String>>,
+    pub size_curves: Option<Vec<f64>>,
+    pub petals_curves: Option<Vec<usize>>,
+    pub width_curves: Option<f64>,
+    pub scale_flow: Option<f64>,
+    pub vortices_flow: Option<usize>,
+    pub width_flow: Option<f64>,
+    pub kind_fractal: Option<String>,
+    pub iterations_fractal: Option<usize>,
+    pub bands_gradient: Option<bool>,
+    pub jitter_gradient: Option<f64>,
+    pub rule_automaton: Option<String>,
+    pub steps_automaton: Option<usize>,
+    pub threshold_automaton: Option<usize>,
+    pub birth_automaton: Option<Vec<usize>>,
+    pub survive_automaton: Option<Vec<usize>>,
+}
+
+/// Pattern drawn above or below the main one
+#[derive(Deserialize, Debug)]
+pub struct ConfigLayer {
+    pub shapes: Option<Value>,
+    pub themes: Option<Vec<String>>,
+    pub z: Option<isize>,
+    /// Pattern options that replace those of `[data.patterns]` for this layer
+    #[serde(flatten)]
+    pub patterns: Map<String, Value>,
 }
 
 /// Entry for a single theme/time combination
@@ -120,6 +314,7 @@ pub struct ConfigEntry {
     pub themes: Option<Vec<String>>,
     pub shapes: Option<Vec<String>>,
     pub line_color: Option<String>,
+    pub filter: Option<ConfigFilter>,
 }
 
 impl MetaConfig {
@@ -136,7 +331,7 @@ impl MetaConfig {
     }
 
     /// Choose options at random according to configuration
-    pub fn pick_cfg(self, rng: &mut ThreadRng, time: usize, verbose: Verbosity) -> SceneCfg {
+    pub fn pick_cfg(self, rng: &mut StdRng, time: usize, verbose: Verbosity) -> SceneCfg {
         // Read default/overriden global options
         let (deviation, distance, size, width, height) = {
             let (deviation, distance, size, width, height);
@@ -221,6 +416,36 @@ impl MetaConfig {
             (deviation, distance, size, width, height)
         };
 
+        // Spatially coherent noise replaces independent per-tile noise if specified
+        let noise = self.noise.map(|n| Noise {
+            kind: match n.kind.as_deref() {
+                None => NoiseKind::Perlin,
+                Some(k) => NoiseKind::from(k).unwrap_or_else(|| {
+                    if verbose.warn {
+                        println!(
+                            "Unknown noise kind '{}', use one of 'value', 'perlin', 'simplex'",
+                            k
+                        );
+                    }
+                    NoiseKind::Perlin
+                }),
+            },
+            seed: 0,
+            scale: n.scale.unwrap_or(NOISE_SCALE),
+            octaves: n.octaves.unwrap_or(NOISE_OCTAVES),
+        });
+        if verbose.details {
+            println!("Noise: {:?}", noise);
+        }
+
+        let lighting = self.lighting.map(|l| Lighting {
+            angle: l.angle.unwrap_or(LIGHT_ANGLE),
+            strength: l.strength.unwrap_or(LIGHT_STRENGTH),
+        });
+        if verbose.details {
+            println!("Lighting: {:?}", lighting);
+        }
+
         // Get list of named colors
         let colors = {
             let mut colors = HashMap::new();
@@ -281,17 +506,36 @@ impl MetaConfig {
             shapes
         };
 
-        let (theme, shape, line_color_override) = choose_theme_shapes(rng, &self.entry, time);
+        let (theme, shape, line_color_override, entry_filter) =
+            choose_theme_shapes(rng, &self.entry, time);
         if verbose.info {
             println!("Chosen theme: '{}'", &theme);
         }
 
-        let (tiling, pattern) = match shapes.get(&shape) {
-            None => (Tiling::choose(rng), Pattern::choose(rng)),
-            Some(t) => (
-                t.1.choose(rng).unwrap_or_else(|| Tiling::choose(rng)),
-                t.0.choose(rng).unwrap_or_else(|| Pattern::choose(rng)),
-            ),
+        let (tiling, pattern, stacked) = match shapes.get(&shape) {
+            None => (Tiling::choose(rng), Pattern::choose(rng), Vec::new()),
+            Some(t) => {
+                if verbose.details {
+                    let resolved = ResolvedShapes {
+                        patterns: &t.0,
+                        tilings: &t.1,
+                        layers: &t.2,
+                    };
+                    match toml::to_string(&resolved) {
+                        Ok(s) => println!("Resolved shapes '{}':\n{}", shape, s),
+                        Err(e) => {
+                            println!("Resolved shapes '{}' could not be dumped: {}", shape, e)
+                        }
+                    }
+                }
+                (
+                    t.1.choose(rng).unwrap_or_else(|| Tiling::choose(rng)),
+                    t.0.choose(rng).unwrap_or_else(|| Pattern::choose(rng)),
+                    t.2.iter()
+                        .map(|l| l.choose(rng).unwrap_or_else(|| Pattern::choose(rng)))
+                        .collect::<Vec<_>>(),
+                )
+            }
         };
         if verbose.info {
             println!(
@@ -301,91 +545,51 @@ impl MetaConfig {
         }
 
         // Get pattern-specific information according to picked shapes
-        let (nb_pattern, var_stripes, width_pattern, tightness_spiral) = {
-            let nb_pattern;
-            let (mut var_stripes, mut width_pattern, mut tightness_spiral) = (0, 0.0, 0.0);
-            if let Some(ConfigData {
-                patterns: Some(p),
-                tilings: _,
-            }) = &self.data
-            {
-                match pattern {
-                    Pattern::FreeCircles => {
-                        nb_pattern = p.nb_free_circles.unwrap_or(NB_FREE_CIRCLES);
-                    }
-                    Pattern::FreeTriangles => {
-                        nb_pattern = p.nb_free_triangles.unwrap_or(NB_FREE_TRIANGLES);
-                    }
-                    Pattern::FreeStripes => {
-                        nb_pattern = p.nb_free_stripes.unwrap_or(NB_FREE_STRIPES);
-                        width_pattern = p.width_stripe.unwrap_or(WIDTH_STRIPE);
-                    }
-                    Pattern::FreeSpirals => {
-                        nb_pattern = p.nb_free_spirals.unwrap_or(NB_FREE_SPIRALS);
-                        width_pattern = p.width_spiral.unwrap_or(WIDTH_SPIRAL);
-                        tightness_spiral = p.tightness_spiral.unwrap_or(TIGHTNESS_SPIRAL);
-                    }
-                    Pattern::ConcentricCircles => {
-                        nb_pattern = p.nb_concentric_circles.unwrap_or(NB_CONCENTRIC_CIRCLES);
-                    }
-                    Pattern::ParallelStripes => {
-                        nb_pattern = p.nb_parallel_stripes.unwrap_or(NB_PARALLEL_STRIPES);
-                        var_stripes = p.var_parallel_stripes.unwrap_or(VAR_PARALLEL_STRIPES);
-                    }
-                    Pattern::CrossedStripes => {
-                        nb_pattern = p.nb_crossed_stripes.unwrap_or(NB_CROSSED_STRIPES);
-                        var_stripes = p.var_crossed_stripes.unwrap_or(VAR_CROSSED_STRIPES);
-                    }
-                    Pattern::ParallelWaves => {
-                        nb_pattern = p.nb_parallel_waves.unwrap_or(NB_PARALLEL_WAVES);
-                        width_pattern = p.width_wave.unwrap_or(WIDTH_WAVE);
-                    }
-                    Pattern::ParallelSawteeth => {
-                        nb_pattern = p.nb_parallel_sawteeth.unwrap_or(NB_PARALLEL_SAWTEETH);
-                        width_pattern = p.width_sawtooth.unwrap_or(WIDTH_SAWTOOTH);
-                    }
-                }
-            } else {
-                match pattern {
-                    Pattern::FreeCircles => nb_pattern = NB_FREE_CIRCLES,
-                    Pattern::FreeTriangles => nb_pattern = NB_FREE_TRIANGLES,
-                    Pattern::FreeStripes => {
-                        nb_pattern = NB_FREE_STRIPES;
-                        width_pattern = WIDTH_STRIPE;
-                    }
-                    Pattern::FreeSpirals => {
-                        nb_pattern = NB_FREE_SPIRALS;
-                        width_pattern = WIDTH_SPIRAL;
-                        tightness_spiral = TIGHTNESS_SPIRAL;
-                    }
-                    Pattern::ConcentricCircles => nb_pattern = NB_CONCENTRIC_CIRCLES,
-                    Pattern::ParallelStripes => {
-                        nb_pattern = NB_PARALLEL_STRIPES;
-                        var_stripes = VAR_PARALLEL_STRIPES;
-                    }
-                    Pattern::CrossedStripes => {
-                        nb_pattern = NB_CROSSED_STRIPES;
-                        var_stripes = VAR_CROSSED_STRIPES;
-                    }
-                    Pattern::ParallelWaves => {
-                        nb_pattern = NB_PARALLEL_WAVES;
-                        width_pattern = WIDTH_WAVE;
-                    }
-                    Pattern::ParallelSawteeth => {
-                        nb_pattern = NB_PARALLEL_SAWTEETH;
-                        width_pattern = WIDTH_SAWTOOTH;
-                    }
-                }
+        let params = pattern_params(
+            pattern,
+            self.data.as_ref().and_then(|d| d.patterns.as_ref()),
+            verbose,
+        );
+
+        // Patterns of the shapes stacked above the main one, then those of [[layer]] tables
+        let layers = {
+            let base = self.data.as_ref().and_then(|d| d.patterns.as_ref());
+            let mut layers = stacked
+                .into_iter()
+                .enumerate()
+                .map(|(i, pattern)| {
+                    let params = pattern_params(pattern, base, verbose);
+                    (i as isize + 1, pattern, None, params)
+                })
+                .collect::<Vec<_>>();
+            for l in self.layer.iter().flatten() {
+                let pattern = l
+                    .shapes
+                    .as_ref()
+                    .and_then(|sh| shapes_from_value(sh, &shapes).0.choose(rng))
+                    .unwrap_or_else(|| Pattern::choose(rng));
+                let theme = l
+                    .themes
+                    .as_ref()
+                    .and_then(|th| th.choose(rng))
+                    .and_then(|name| {
+                        let theme = themes.get(name).cloned();
+                        if theme.is_none() && verbose.warn {
+                            println!("Unknown theme '{}' in layer", name);
+                        }
+                        theme
+                    });
+                let patterns = l.get_patterns(base, verbose);
+                let params = pattern_params(pattern, patterns.as_ref(), verbose);
+                let z = l.z.unwrap_or(layers.len() as isize + 1);
+                layers.push((z, pattern, theme, params));
             }
-            if verbose.details {
-                println!(
-                    "Number of patterns: {}
-Variability of stripes orientation: {}
-Width of pattern: {}",
-                    nb_pattern, var_stripes, width_pattern
-                );
+            if verbose.info {
+                for (z, pattern, _, _) in &layers {
+                    println!("Layer with pattern '{:?}' at z = {}", pattern, z);
+                }
             }
-            (nb_pattern, var_stripes, width_pattern, tightness_spiral)
+            layers
         };
 
         if themes.is_empty() {
@@ -421,6 +625,35 @@ Width of pattern: {}",
             }
         }
 
+        // Layout of the bricks tiling
+        let (ratio_bricks, offset_bricks) =
+            match self.data.as_ref().and_then(|d| d.tilings.as_ref()) {
+                Some(t) => (
+                    t.ratio_bricks.unwrap_or(RATIO_BRICKS),
+                    t.offset_bricks.unwrap_or(OFFSET_BRICKS),
+                ),
+                None => (RATIO_BRICKS, OFFSET_BRICKS),
+            };
+        let ratio_bricks = if ratio_bricks > 0. {
+            ratio_bricks
+        } else {
+            if verbose.warn {
+                println!(
+                    "Invalid brick ratio {}, must be positive. Using default instead.",
+                    ratio_bricks
+                );
+            }
+            RATIO_BRICKS
+        };
+        // Shifting rows by a whole brick changes nothing
+        let offset_bricks = offset_bricks.rem_euclid(1.);
+        if verbose.details && matches!(tiling, Tiling::Bricks) {
+            println!(
+                "Brick ratio: {}
+Brick offset: {}",
+                ratio_bricks, offset_bricks
+            );
+        }
         // Get tiling-specific options according to picked shapes
         let (size_tiling, nb_delaunay) = {
             if let Some(ConfigData {
@@ -435,6 +668,18 @@ Width of pattern: {}",
                     Tiling::SquaresAndTriangles => (t.size_squ_and_tri.unwrap_or(size), 0),
                     Tiling::Rhombus => (t.size_rho.unwrap_or(size), 0),
                     Tiling::Pentagons(_) => (t.size_pen.unwrap_or(size), 0),
+                    Tiling::Squares => (t.size_squ.unwrap_or(size), 0),
+                    Tiling::Bricks => (t.size_bri.unwrap_or(size), 0),
+                    Tiling::Herringbone => (t.size_her.unwrap_or(size), 0),
+                    Tiling::Basketweave => (t.size_bas.unwrap_or(size), 0),
+                    Tiling::OctagonsAndSquares => (t.size_oct_and_squ.unwrap_or(size), 0),
+                    Tiling::DodecagonsHexagonsAndSquares => {
+                        (t.size_dod_hex_and_squ.unwrap_or(size), 0)
+                    }
+                    Tiling::DodecagonsAndTriangles => (t.size_dod_and_tri.unwrap_or(size), 0),
+                    Tiling::ElongatedTriangles => (t.size_elo_tri.unwrap_or(size), 0),
+                    Tiling::SnubSquares => (t.size_snub_squ.unwrap_or(size), 0),
+                    Tiling::SnubHexagons => (t.size_snub_hex.unwrap_or(size), 0),
                     Tiling::Delaunay => (0.0, t.nb_delaunay.unwrap_or(NB_DELAUNAY)),
                 }
             } else {
@@ -445,6 +690,16 @@ Width of pattern: {}",
                     Tiling::SquaresAndTriangles => (size, 0),
                     Tiling::Rhombus => (size, 0),
                     Tiling::Pentagons(_) => (size, 0),
+                    Tiling::Squares => (size, 0),
+                    Tiling::Bricks => (size, 0),
+                    Tiling::Herringbone => (size, 0),
+                    Tiling::Basketweave => (size, 0),
+                    Tiling::OctagonsAndSquares => (size, 0),
+                    Tiling::DodecagonsHexagonsAndSquares => (size, 0),
+                    Tiling::DodecagonsAndTriangles => (size, 0),
+                    Tiling::ElongatedTriangles => (size, 0),
+                    Tiling::SnubSquares => (size, 0),
+                    Tiling::SnubHexagons => (size, 0),
                     Tiling::Delaunay => (0.0, NB_DELAUNAY),
                 }
             }
@@ -471,7 +726,151 @@ Line color: {}",
             );
         }
 
-        SceneCfg {
+        // Entry-specific filter settings override the global ones
+        let filter = {
+            let global = self
+                .filter
+                .map(|f| f.get_settings(&colors, verbose))
+                .unwrap_or_default();
+            match entry_filter {
+                None => global,
+                Some(f) => f.get_settings(&colors, verbose).over(global),
+            }
+        };
+        if verbose.details {
+            println!("Filter: {:?}", filter);
+        }
+
+        // Items let those below contribute to the color only if blending is enabled,
+        // each layer with the settings of its own pattern
+        let config_blend = self.blend;
+        let blend_of = |pattern| {
+            config_blend
+                .as_ref()
+                .map(|b| b.get_settings(pattern, verbose))
+        };
+        let blend = blend_of(pattern);
+        if verbose.details {
+            println!("Blend: {:?}", blend);
+        }
+
+        let coverage = self.coverage.map(|c| Coverage {
+            mode: match c.mode.as_deref() {
+                None => CoverageMode::Majority,
+                Some(m) => CoverageMode::from(m).unwrap_or_else(|| {
+                    if verbose.warn {
+                        println!(
+                            "Unknown coverage mode '{}', use one of 'majority', 'blend'",
+                            m
+                        );
+                    }
+                    CoverageMode::Majority
+                }),
+            },
+            samples: c.samples.unwrap_or(COVERAGE_SAMPLES).max(1),
+            split: c.split.unwrap_or(0),
+        });
+        if verbose.details {
+            println!("Coverage: {:?}", coverage);
+        }
+
+        let csg = self.csg.map(|c| Csg {
+            op: match c.op.as_deref() {
+                None => CsgOp::Difference,
+                Some(o) => CsgOp::from(o).unwrap_or_else(|| {
+                    if verbose.warn {
+                        println!(
+                            "Unknown operation '{}', use one of 'union', 'intersection', 'difference', 'xor'",
+                            o
+                        );
+                    }
+                    CsgOp::Difference
+                }),
+            },
+            operand: match c.operand.as_deref() {
+                None => Operand::Inner,
+                Some(o) => Operand::from(o).unwrap_or_else(|| {
+                    if verbose.warn {
+                        println!("Unknown operand '{}', use one of 'inner', 'disc', 'next'", o);
+                    }
+                    Operand::Inner
+                }),
+            },
+            ratio: c.ratio.unwrap_or(CSG_RATIO),
+        });
+        if verbose.details {
+            println!("CSG: {:?}", csg);
+        }
+
+        let transform = self.transform.map(|t| {
+            let (sx, sy) = match t.scale.as_deref() {
+                None => (1., 1.),
+                Some(&[s]) => (s, s),
+                Some(&[sx, sy]) => (sx, sy),
+                Some(s) => {
+                    if verbose.warn {
+                        println!("Invalid scale {:?}. Expected one or two numbers.", s);
+                    }
+                    (1., 1.)
+                }
+            };
+            let matrix = Matrix::scale(sx, sy)
+                .then(Matrix::skew(t.skew.unwrap_or(0.)))
+                .then(Matrix::rotation(t.rotate.unwrap_or(0)));
+            Transform {
+                matrix: if matrix.inverse().is_some() {
+                    matrix
+                } else {
+                    if verbose.warn {
+                        println!("Invalid transform {:?}: it flattens the plane", matrix.0);
+                    }
+                    Matrix::identity()
+                },
+                jitter: t.jitter.unwrap_or(0).abs(),
+            }
+        });
+        if verbose.details {
+            println!("Transform: {:?}", transform);
+        }
+
+        let feather = self.feather.map(|f| Feather {
+            mode: match f.mode.as_deref() {
+                None => FeatherMode::Blend,
+                Some(m) => FeatherMode::from(m).unwrap_or_else(|| {
+                    if verbose.warn {
+                        println!("Unknown feather mode '{}', use one of 'blend', 'dither'", m);
+                    }
+                    FeatherMode::Blend
+                }),
+            },
+            width: f.width.unwrap_or(FEATHER_WIDTH).max(0.),
+        });
+        if verbose.details {
+            println!("Feather: {:?}", feather);
+        }
+
+        let background = self.background.map(|b| Background {
+            shape: match b.shape.as_deref() {
+                None => GradientShape::Linear,
+                Some(s) => GradientShape::from(s).unwrap_or_else(|| {
+                    if verbose.warn {
+                        println!(
+                            "Unknown gradient shape '{}', use one of 'linear', 'radial'",
+                            s
+                        );
+                    }
+                    GradientShape::Linear
+                }),
+            },
+            colors: b.colors.unwrap_or(BACKGROUND_COLORS).max(1),
+            banded: b.bands.unwrap_or(false),
+            jitter: b.jitter.unwrap_or(0.).clamp(0., 1.),
+        });
+        if verbose.details {
+            println!("Background: {:?}", background);
+        }
+
+        let mut cfg = SceneCfg {
             deviation,
             distance,
             theme: themes
@@ -496,16 +895,322 @@ Line color: {}",
                         .unwrap_or(Color(0, 0, 0))
                 }),
             pattern,
-            nb_pattern,
-            var_stripes,
+            params,
             nb_delaunay,
+            ratio_bricks,
+            offset_bricks,
             size_tiling,
-            width_pattern,
-            tightness_spiral,
+            noise,
+            lighting,
+            filter,
+            blend,
+            coverage,
+            csg,
+            transform,
+            feather,
+            background,
+            layers: Vec::new(),
+        };
+        cfg.layers = layers
+            .into_iter()
+            .map(|(z, pattern, theme, params)| {
+                let blend = blend_of(pattern);
+                if verbose.details {
+                    println!("Blend of layer '{:?}': {:?}", pattern, blend);
+                }
+                Layer {
+                    z,
+                    cfg: cfg.with_pattern(pattern, theme, params, blend),
+                }
+            })
+            .collect();
+        cfg
+    }
+}
+
+impl SceneCfg {
+    /// Same scene with another pattern, its own blend, and possibly another theme
+    fn with_pattern(
+        &self,
+        pattern: Pattern,
+        theme: Option<Chooser<ThemeItem>>,
+        params: PatternParams,
+        blend: Option<Blend>,
+    ) -> Self {
+        Self {
+            theme: theme.unwrap_or_else(|| self.theme.clone()),
+            pattern,
+            params,
+            blend,
+            layers: Vec::new(),
+            ..self.clone()
         }
     }
 }
 
+impl ConfigLayer {
+    /// Options of the main pattern, overridden by those of the layer
+    fn get_patterns(
+        &self,
+        base: Option<&ConfigPatterns>,
+        verbose: Verbosity,
+    ) -> Option<ConfigPatterns> {
+        if self.patterns.is_empty() {
+            return base.cloned();
+        }
+        let mut table = base
+            .and_then(|p| Value::try_from(p).ok())
+            .and_then(|v| v.as_table().cloned())
+            .unwrap_or_default();
+        for (k, v) in &self.patterns {
+            table.insert(k.clone(), v.clone());
+        }
+        Value::Table(table)
+            .try_into()
+            .map_err(|e| {
+                if verbose.warn {
+                    println!("Invalid options in layer: {}", e);
+                }
+            })
+            .ok()
+            .or_else(|| base.cloned())
+    }
+}
+
+/// Get pattern-specific information, from the options if there are any
+fn pattern_params(
+    pattern: Pattern,
+    patterns: Option<&ConfigPatterns>,
+    verbose: Verbosity,
+) -> PatternParams {
+    let mut params = PatternParams {
+        nb_pattern: 0,
+        var_stripes: 0,
+        width_pattern: 0.0,
+        tightness_spiral: 0.0,
+        weight_voronoi: 0.0,
+        metric_voronoi: Metric::Euclidean,
+        scale_contours: 0.0,
+        octaves_contours: 0,
+        terrace_contours: false,
+        sides_polygons: SIDES_POLYGONS,
+        star_polygons: STAR_POLYGONS,
+        rotation_polygons: ROTATION_POLYGONS,
+        size_polygons: SIZE_POLYGONS,
+        distribution_polygons: SizeDistribution::Linear,
+        center_rays: RayCenter::Random,
+        jitter_rays: 0.0,
+        twist_rays: 0,
+        alternate_rays: true,
+        centers_arcs: CENTERS_ARCS,
+        gap_arcs: GAP_ARCS,
+        width_arcs: WIDTH_ARCS,
+        families_curves: Vec::new(),
+        size_curves: SIZE_CURVES,
+        petals_curves: PETALS_CURVES,
+        width_curves: WIDTH_CURVES,
+        scale_flow: SCALE_FLOW,
+        vortices_flow: 0,
+        width_flow: WIDTH_FLOW,
+        kind_fractal: None,
+        iterations_fractal: ITERATIONS_FRACTAL,
+        bands_gradient: false,
+        jitter_gradient: 0.0,
+        rule_automaton: Rule::Majority,
+        steps_automaton: STEPS_AUTOMATON,
+    };
+    if let Some(p) = patterns {
+        match pattern {
+            Pattern::FreeCircles => {
+                params.nb_pattern = p.nb_free_circles.unwrap_or(NB_FREE_CIRCLES);
+            }
+            Pattern::FreeTriangles => {
+                params.nb_pattern = p.nb_free_triangles.unwrap_or(NB_FREE_TRIANGLES);
+            }
+            Pattern::FreePolygons => {
+                params.nb_pattern = p.nb_free_polygons.unwrap_or(NB_FREE_POLYGONS);
+                (
+                    params.sides_polygons,
+                    params.star_polygons,
+                    params.rotation_polygons,
+                    params.size_polygons,
+                    params.distribution_polygons,
+                ) = p.get_polygons(verbose);
+            }
+            Pattern::FreeStripes => {
+                params.nb_pattern = p.nb_free_stripes.unwrap_or(NB_FREE_STRIPES);
+                params.width_pattern = p.width_stripe.unwrap_or(WIDTH_STRIPE);
+            }
+            Pattern::FreeSpirals => {
+                params.nb_pattern = p.nb_free_spirals.unwrap_or(NB_FREE_SPIRALS);
+                params.width_pattern = p.width_spiral.unwrap_or(WIDTH_SPIRAL);
+                params.tightness_spiral = p.tightness_spiral.unwrap_or(TIGHTNESS_SPIRAL);
+            }
+            Pattern::Curves => {
+                params.nb_pattern = p.nb_curves.unwrap_or(NB_CURVES);
+                (
+                    params.families_curves,
+                    params.size_curves,
+                    params.petals_curves,
+                    params.width_curves,
+                ) = p.get_curves(verbose);
+            }
+            Pattern::ConcentricCircles => {
+                params.nb_pattern = p.nb_concentric_circles.unwrap_or(NB_CONCENTRIC_CIRCLES);
+            }
+            Pattern::RadialRays => {
+                params.nb_pattern = p.nb_radial_rays.unwrap_or(NB_RADIAL_RAYS);
+                if let Some(c) = &p.center_rays {
+                    params.center_rays = RayCenter::from(c).unwrap_or_else(|| {
+                        if verbose.warn {
+                            println!(
+                                "Unknown center '{}', use one of 'random', 'center', 'thirds'",
+                                c
+                            );
+                        }
+                        RayCenter::Random
+                    });
+                }
+                params.jitter_rays = p.jitter_rays.unwrap_or(0.).clamp(0., 1.);
+                params.twist_rays = p.twist_rays.unwrap_or(0);
+                params.alternate_rays = p.alternate_rays.unwrap_or(true);
+            }
+            Pattern::Arcs => {
+                params.nb_pattern = p.nb_arcs.unwrap_or(NB_ARCS);
+                params.centers_arcs = p.centers_arcs.unwrap_or(CENTERS_ARCS);
+                params.gap_arcs = p.gap_arcs.unwrap_or(GAP_ARCS).clamp(0., 1.);
+                params.width_arcs = p.width_arcs.unwrap_or(WIDTH_ARCS).clamp(0., 1.);
+            }
+            Pattern::ParallelStripes => {
+                params.nb_pattern = p.nb_parallel_stripes.unwrap_or(NB_PARALLEL_STRIPES);
+                params.var_stripes = p.var_parallel_stripes.unwrap_or(VAR_PARALLEL_STRIPES);
+            }
+            Pattern::CrossedStripes => {
+                params.nb_pattern = p.nb_crossed_stripes.unwrap_or(NB_CROSSED_STRIPES);
+                params.var_stripes = p.var_crossed_stripes.unwrap_or(VAR_CROSSED_STRIPES);
+            }
+            Pattern::ParallelWaves => {
+                params.nb_pattern = p.nb_parallel_waves.unwrap_or(NB_PARALLEL_WAVES);
+                params.width_pattern = p.width_wave.unwrap_or(WIDTH_WAVE);
+            }
+            Pattern::ParallelSawteeth => {
+                params.nb_pattern = p.nb_parallel_sawteeth.unwrap_or(NB_PARALLEL_SAWTEETH);
+                params.width_pattern = p.width_sawtooth.unwrap_or(WIDTH_SAWTOOTH);
+            }
+            Pattern::Voronoi => {
+                params.nb_pattern = p.nb_voronoi.unwrap_or(NB_VORONOI);
+                params.weight_voronoi = p.weight_voronoi.unwrap_or(WEIGHT_VORONOI).max(0.);
+                if let Some(m) = &p.metric_voronoi {
+                    params.metric_voronoi = Metric::from(m).unwrap_or_else(|| {
+                        if verbose.warn {
+                            println!(
+                                "Unknown metric '{}', use one of 'euclidean', 'manhattan', 'chebyshev'",
+                                m
+                            );
+                        }
+                        Metric::Euclidean
+                    });
+                }
+            }
+            Pattern::Contours => {
+                params.nb_pattern = p.nb_contours.unwrap_or(NB_CONTOURS);
+                params.scale_contours = p.scale_contours.unwrap_or(SCALE_CONTOURS);
+                params.octaves_contours = p.octaves_contours.unwrap_or(OCTAVES_CONTOURS);
+                params.terrace_contours = p.terrace_contours.unwrap_or(false);
+            }
+            Pattern::FlowField => {
+                params.nb_pattern = p.nb_flow_field.unwrap_or(NB_FLOW_FIELD);
+                params.scale_flow = p.scale_flow.unwrap_or(SCALE_FLOW);
+                params.vortices_flow = p.vortices_flow.unwrap_or(0);
+                params.width_flow = p.width_flow.unwrap_or(WIDTH_FLOW).max(1e-3);
+            }
+            Pattern::Fractal => {
+                params.nb_pattern = p.nb_fractal.unwrap_or(NB_FRACTAL);
+                params.kind_fractal = p.kind_fractal.as_deref().and_then(|k| {
+                    let kind = FractalKind::from(k);
+                    if kind.is_none() && verbose.warn {
+                        println!("Unknown fractal '{}', use one of 'julia', 'mandelbrot'", k);
+                    }
+                    kind
+                });
+                params.iterations_fractal = p.iterations_fractal.unwrap_or(ITERATIONS_FRACTAL);
+            }
+            Pattern::LinearGradient => {
+                params.nb_pattern = p.nb_linear_gradient.unwrap_or(NB_LINEAR_GRADIENT);
+                params.bands_gradient = p.bands_gradient.unwrap_or(false);
+                params.jitter_gradient = p.jitter_gradient.unwrap_or(0.).clamp(0., 1.);
+            }
+            Pattern::RadialGradient => {
+                params.nb_pattern = p.nb_radial_gradient.unwrap_or(NB_RADIAL_GRADIENT);
+                params.bands_gradient = p.bands_gradient.unwrap_or(false);
+                params.jitter_gradient = p.jitter_gradient.unwrap_or(0.).clamp(0., 1.);
+            }
+            Pattern::Automaton => {
+                params.nb_pattern = p.nb_automaton.unwrap_or(NB_AUTOMATON);
+                (params.rule_automaton, params.steps_automaton) = p.get_automaton(verbose);
+            }
+        }
+    } else {
+        match pattern {
+            Pattern::FreeCircles => params.nb_pattern = NB_FREE_CIRCLES,
+            Pattern::FreeTriangles => params.nb_pattern = NB_FREE_TRIANGLES,
+            Pattern::FreePolygons => params.nb_pattern = NB_FREE_POLYGONS,
+            Pattern::FreeStripes => {
+                params.nb_pattern = NB_FREE_STRIPES;
+                params.width_pattern = WIDTH_STRIPE;
+            }
+            Pattern::FreeSpirals => {
+                params.nb_pattern = NB_FREE_SPIRALS;
+                params.width_pattern = WIDTH_SPIRAL;
+                params.tightness_spiral = TIGHTNESS_SPIRAL;
+            }
+            Pattern::Curves => params.nb_pattern = NB_CURVES,
+            Pattern::ConcentricCircles => params.nb_pattern = NB_CONCENTRIC_CIRCLES,
+            Pattern::RadialRays => params.nb_pattern = NB_RADIAL_RAYS,
+            Pattern::Arcs => params.nb_pattern = NB_ARCS,
+            Pattern::ParallelStripes => {
+                params.nb_pattern = NB_PARALLEL_STRIPES;
+                params.var_stripes = VAR_PARALLEL_STRIPES;
+            }
+            Pattern::CrossedStripes => {
+                params.nb_pattern = NB_CROSSED_STRIPES;
+                params.var_stripes = VAR_CROSSED_STRIPES;
+            }
+            Pattern::ParallelWaves => {
+                params.nb_pattern = NB_PARALLEL_WAVES;
+                params.width_pattern = WIDTH_WAVE;
+            }
+            Pattern::ParallelSawteeth => {
+                params.nb_pattern = NB_PARALLEL_SAWTEETH;
+                params.width_pattern = WIDTH_SAWTOOTH;
+            }
+            Pattern::Voronoi => {
+                params.nb_pattern = NB_VORONOI;
+                params.weight_voronoi = WEIGHT_VORONOI;
+            }
+            Pattern::Contours => {
+                params.nb_pattern = NB_CONTOURS;
+                params.scale_contours = SCALE_CONTOURS;
+                params.octaves_contours = OCTAVES_CONTOURS;
+            }
+            Pattern::FlowField => params.nb_pattern = NB_FLOW_FIELD,
+            Pattern::Fractal => params.nb_pattern = NB_FRACTAL,
+            Pattern::LinearGradient => params.nb_pattern = NB_LINEAR_GRADIENT,
+            Pattern::RadialGradient => params.nb_pattern = NB_RADIAL_GRADIENT,
+            Pattern::Automaton => params.nb_pattern = NB_AUTOMATON,
+        }
+    }
+    if verbose.details {
+        println!(
+            "Number of patterns: {}
+Variability of stripes orientation: {}
+Width of pattern: {}",
+            params.nb_pattern, params.var_stripes, params.width_pattern
+        );
+    }
+    params
+}
+
 /// Parse a color code: decimal (0-255) or hex (00-FF)
 fn color_from_value(val: &Value, dict: &HashMap<String, Color>) -> Result<Color, String> {
     match val {
@@ -513,23 +1218,12 @@ fn color_from_value(val: &Value, dict: &HashMap<String, Color>) -> Result<Color,
             if let Some(color) = dict.get(s.as_str()) {
                 return Ok(*color);
             }
-            if s.len() == 7 && &s[0..1] == "#" {
-                let r = usize::from_str_radix(&s[1..3], 16);
-                let g = usize::from_str_radix(&s[3..5], 16);
-                let b = usize::from_str_radix(&s[5..7], 16);
-                match (r, g, b) {
-                    (Ok(r), Ok(g), Ok(b)) => Ok(Color(r, g, b)),
-                    _ => Err(format!(
-                        "{:?} is not a valid color format.\nUse [0, 0, 255] or \"#0000FF\"",
-                        s
-                    )),
-                }
-            } else {
-                Err(format!(
+            Color::from_hex(s).ok_or_else(|| {
+                format!(
                     "{:?} is not a valid color format.\nUse [0, 0, 255] or \"#0000FF\"",
                     s
-                ))
-            }
+                )
+            })
         }
         Value::Array(arr) => {
             if arr.len() != 3 {
@@ -559,7 +1253,7 @@ fn theme_item_from_value(
     val: &Value,
     dict: &HashMap<String, Color>,
     verbose: Verbosity,
-) -> (ThemeItem, usize) {
+) -> (ThemeItem, f64) {
     let warn_invalid = |x| {
         if verbose.warn {
             println!(
@@ -654,8 +1348,8 @@ Note that the format [<R>, <G>, <B>] is not accepted here",
             })
             .map(|n| n.max(0) as usize);
             let wht = match map.get("weight") {
-                Some(Value::Integer(w)) => *w.max(&0) as usize,
-                Some(Value::Float(w)) => w.round().max(0.0) as usize,
+                Some(Value::Integer(w)) => *w.max(&0) as f64,
+                Some(Value::Float(w)) => w.max(0.),
                 Some(x) => {
                     if verbose.warn {
                         println!("Not a valid weight: {:?}", x);
@@ -673,7 +1367,7 @@ Note that the format [<R>, <G>, <B>] is not accepted here",
                             let color = tbl
                                 .get("color")
                                 .map(|v| {
-                                    color_from_value(v, &dict).unwrap_or_else(|_| {
+                                    color_from_value(v, dict).unwrap_or_else(|_| {
                                         if verbose.warn {
                                             println!("Invalid color: {:?}", v)
                                         }
@@ -694,31 +1388,61 @@ Note that the format [<R>, <G>, <B>] is not accepted here",
                             };
                             let variability = match tbl.get("variability") {
                                 None => 0,
-                                Some(Value::Integer(n)) => {
-                                    if *n > 0 {
-                                        *n as usize
-                                    } else {
-                                        0
+                                Some(Value::Integer(n)) if *n > 0 => *n as usize,
+                                Some(Value::Integer(_)) => 0,
+                                Some(Value::Float(f)) if *f > 0. => f.round() as usize,
+                                Some(Value::Float(_)) => 0,
+                                Some(v) => {
+                                    if verbose.warn {
+                                        println!("Not a valid variability: {:?}", v);
                                     }
+                                    0
                                 }
-                                Some(Value::Float(f)) => {
-                                    if *f > 0. {
-                                        f.round() as usize
-                                    } else {
-                                        0
-                                    }
+                            };
+                            let cluster = match tbl.get("cluster") {
+                                None => None,
+                                Some(Value::Table(c)) => {
+                                    let size = match c.get("size") {
+                                        None => CLUSTER_SIZE,
+                                        Some(Value::Integer(n)) => (*n).max(1) as f64,
+                                        Some(Value::Float(f)) => f.max(1.),
+                                        Some(v) => {
+                                            if verbose.warn {
+                                                println!("Not a valid cluster size: {:?}", v);
+                                            }
+                                            CLUSTER_SIZE
+                                        }
+                                    };
+                                    let mode = match c.get("mode") {
+                                        None => ClusterMode::Blob,
+                                        Some(Value::String(s)) => ClusterMode::from(s)
+                                            .unwrap_or_else(|| {
+                                                if verbose.warn {
+                                                    println!("Unknown cluster mode '{}', use one of 'blob', 'streak'", s);
+                                                }
+                                                ClusterMode::Blob
+                                            }),
+                                        Some(v) => {
+                                            if verbose.warn {
+                                                println!("Not a valid cluster mode: {:?}", v);
+                                            }
+                                            ClusterMode::Blob
+                                        }
+                                    };
+                                    Some(Cluster::new(mode, size))
                                 }
                                 Some(v) => {
                                     if verbose.warn {
-                                        println!("Not a valid variability: {:?}", v);
+                                        println!("Invalid cluster. Expected a table: {:?}", v);
                                     }
-                                    0
+                                    None
                                 }
                             };
                             salt.0.push(SaltItem {
                                 color,
                                 likeliness,
                                 variability,
+                                cluster,
                             });
                         }
                     }
@@ -778,21 +1502,30 @@ Provide a theme item or an array of theme items",
     }
 }
 
-fn shapes_from_value(
-    val: &Value,
-    shapes: &HashMap<String, (Chooser<Pattern>, Chooser<Tiling>)>,
-) -> (Chooser<Pattern>, Chooser<Tiling>) {
+/// Patterns and tilings to choose from,
+/// along with the patterns of the layers stacked above, from bottom to top
+type Shapes = (Chooser<Pattern>, Chooser<Tiling>, Vec<Chooser<Pattern>>);
+
+fn shapes_from_value(val: &Value, shapes: &HashMap<String, Shapes>) -> Shapes {
     let mut tilings = Chooser::new(vec![]);
     let mut patterns = Chooser::new(vec![]);
+    let mut layers: Vec<Chooser<Pattern>> = Vec::new();
     match val {
         Value::Array(arr) => {
             for x in arr {
                 match x {
                     Value::String(s) => {
                         if let Some(sh) = shapes.get(s) {
-                            let (p, t) = sh;
+                            let (p, t, l) = sh;
                             tilings.append(t.extract());
                             patterns.append(p.extract());
+                            // Layers of the named shapes are merged level by level
+                            for (i, layer) in l.iter().enumerate() {
+                                match layers.get_mut(i) {
+                                    Some(merged) => merged.append(layer.extract()),
+                                    None => layers.push(layer.clone()),
+                                }
+                            }
                         } else {
                             add_shape(&s[..], BASE_WEIGHT, &mut tilings, &mut patterns);
                         }
@@ -800,8 +1533,11 @@ fn shapes_from_value(
                     Value::Array(a) => {
                         if a.len() == 2 {
                             match &a[..] {
-                                [Value::String(s), Value::Integer(w)] if *w > 0 => {
-                                    add_shape(&s[..], *w as usize, &mut tilings, &mut patterns)
+                                [Value::String(s), Value::Integer(w)] if *w >= 0 => {
+                                    add_shape(&s[..], *w as f64, &mut tilings, &mut patterns)
+                                }
+                                [Value::String(s), Value::Float(w)] if *w >= 0. => {
+                                    add_shape(&s[..], *w, &mut tilings, &mut patterns)
                                 }
                                 _ => println!("{} is not a valid shape.", x),
                             }
@@ -813,14 +1549,44 @@ fn shapes_from_value(
                 }
             }
         }
+        // Several patterns drawn on top of each other
+        Value::Table(t) => {
+            if let Some(sh) = t.get("shapes") {
+                tilings = shapes_from_value(sh, shapes).1;
+            }
+            match t.get("layers") {
+                Some(Value::Array(arr)) if !arr.is_empty() => {
+                    // A layered shape used as a layer brings its own layers above it
+                    let mut stack = arr.iter().flat_map(|x| {
+                        let (p, _, l) = shapes_from_value(&Value::Array(vec![x.clone()]), shapes);
+                        std::iter::once(p).chain(l)
+                    });
+                    patterns = stack.next().unwrap();
+                    layers = stack.collect();
+                }
+                _ => println!("{} is not a valid list of layers.", val),
+            }
+        }
         _ => println!("{} is not an array of shapes.", val),
     }
-    (patterns, tilings)
+    (patterns, tilings, layers)
 }
 
 /// Read shape from one of its names
-fn add_shape(s: &str, w: usize, tilings: &mut Chooser<Tiling>, patterns: &mut Chooser<Pattern>) {
-    match &s[..] {
+fn add_shape(s: &str, w: f64, tilings: &mut Chooser<Tiling>, patterns: &mut Chooser<Pattern>) {
+    if !try_add_shape(s, w, tilings, patterns) {
+        println!("{} is not recognized as a shape", s);
+    }
+}
+
+/// Same as `add_shape`, but tells whether the name is known instead of complaining
+fn try_add_shape(
+    s: &str,
+    w: f64,
+    tilings: &mut Chooser<Tiling>,
+    patterns: &mut Chooser<Pattern>,
+) -> bool {
+    match s {
         "H" | "hex." | "hexagons" => tilings.push(Tiling::Hexagons, w),
         "T" | "tri." | "triangles" => tilings.push(Tiling::Triangles, w),
         "H&T" | "hex.&tri." | "hexagons&squares" => tilings.push(Tiling::HexagonsAndTriangles, w),
@@ -834,26 +1600,52 @@ fn add_shape(s: &str, w: usize, tilings: &mut Chooser<Tiling>, patterns: &mut Ch
         "P4" | "pen.4" | "pentagons-4" => tilings.push(Tiling::Pentagons(4), w),
         "P5" | "pen.5" | "pentagons-5" => tilings.push(Tiling::Pentagons(5), w),
         "P6" | "pen.6" | "pentagons-6" => tilings.push(Tiling::Pentagons(6), w),
+        "S" | "squ." | "squares" => tilings.push(Tiling::Squares, w),
+        "B" | "bri." | "bricks" => tilings.push(Tiling::Bricks, w),
+        "HB" | "her." | "herringbone" => tilings.push(Tiling::Herringbone, w),
+        "BW" | "bas." | "basketweave" => tilings.push(Tiling::Basketweave, w),
+        "O&S" | "oct.&squ." | "octagons&squares" => tilings.push(Tiling::OctagonsAndSquares, w),
+        "D&H&S" | "dod.&hex.&squ." | "dodecagons&hexagons&squares" => {
+            tilings.push(Tiling::DodecagonsHexagonsAndSquares, w)
+        }
+        "D&T" | "dod.&tri." | "dodecagons&triangles" => {
+            tilings.push(Tiling::DodecagonsAndTriangles, w)
+        }
+        "ET" | "e-tri." | "elongated-triangles" => tilings.push(Tiling::ElongatedTriangles, w),
+        "SS" | "s-squ." | "snub-squares" => tilings.push(Tiling::SnubSquares, w),
+        "SH" | "s-hex." | "snub-hexagons" => tilings.push(Tiling::SnubHexagons, w),
         "FC" | "f-cir." | "free-circles" => patterns.push(Pattern::FreeCircles, w),
         "FT" | "f-tri." | "free-triangles" => patterns.push(Pattern::FreeTriangles, w),
+        "FG" | "f-pol." | "free-polygons" => patterns.push(Pattern::FreePolygons, w),
         "FR" | "f-str." | "free-stripes" => patterns.push(Pattern::FreeStripes, w),
         "FP" | "f-spi." | "free-spirals" => patterns.push(Pattern::FreeSpirals, w),
+        "CU" | "cur." | "curves" => patterns.push(Pattern::Curves, w),
         "CC" | "c-cir." | "concentric-circles" => patterns.push(Pattern::ConcentricCircles, w),
+        "RR" | "r-ray." | "radial-rays" => patterns.push(Pattern::RadialRays, w),
+        "AR" | "arc." | "arcs" => patterns.push(Pattern::Arcs, w),
         "PS" | "p-str." | "parallel-stripes" => patterns.push(Pattern::ParallelStripes, w),
         "CS" | "c-str." | "crossed-stripes" => patterns.push(Pattern::CrossedStripes, w),
         "PW" | "p-wav." | "parallel-waves" => patterns.push(Pattern::ParallelWaves, w),
         "PT" | "p-saw." | "parallel-sawteeth" => patterns.push(Pattern::ParallelSawteeth, w),
-        _ => println!("{} is not recognized as a shape", s),
+        "VO" | "vor." | "voronoi" => patterns.push(Pattern::Voronoi, w),
+        "CT" | "con." | "contours" => patterns.push(Pattern::Contours, w),
+        "FF" | "flow." | "flow-field" => patterns.push(Pattern::FlowField, w),
+        "FX" | "frac." | "fractal" => patterns.push(Pattern::Fractal, w),
+        "LG" | "l-grad." | "linear-gradient" => patterns.push(Pattern::LinearGradient, w),
+        "RG" | "r-grad." | "radial-gradient" => patterns.push(Pattern::RadialGradient, w),
+        "CA" | "aut." | "automaton" => patterns.push(Pattern::Automaton, w),
+        _ => return false,
     }
+    true
 }
 
 fn choose_theme_shapes(
-    rng: &mut ThreadRng,
+    rng: &mut StdRng,
     entry: &Option<Vec<ConfigEntry>>,
     time: usize,
-) -> (String, String, String) {
+) -> (String, String, String, Option<ConfigFilter>) {
     match entry {
-        None => (String::from(""), String::from(""), String::from("")),
+        None => (String::from(""), String::from(""), String::from(""), None),
         Some(v) => {
             let mut valid = Chooser::new(vec![]);
             for e in v {
@@ -865,7 +1657,7 @@ fn choose_theme_shapes(
                     .map(String::from)
                     .collect::<Vec<_>>();
                 let start = markers
-                    .get(0)
+                    .first()
                     .as_ref()
                     .unwrap_or(&&String::from("0"))
                     .parse::<usize>()
@@ -877,18 +1669,16 @@ fn choose_theme_shapes(
                     .parse::<usize>()
                     .unwrap_or(2400);
 
-                if start <= time && time <= end {
-                    valid.push(e, e.distance.unwrap_or(BASE_WEIGHT));
-                }
                 // Allow start time to be greater then end time.
                 // That way we can check if time is within 1800-0600 directly.
                 // Otherwise you would need to specify 1800-2400 and 0000-0600
-                else if start > end && (start <= time || time <= end) {
-                    valid.push(e, e.distance.unwrap_or(BASE_WEIGHT));
+                if (start <= time && time <= end) || (start > end && (start <= time || time <= end))
+                {
+                    valid.push(e, e.distance.map(|d| d as f64).unwrap_or(BASE_WEIGHT));
                 }
             }
             match valid.choose(rng) {
-                None => (String::from(""), String::from(""), String::from("")),
+                None => (String::from(""), String::from(""), String::from(""), None),
                 Some(chosen_entry) => {
                     let chosen_theme = match &chosen_entry.themes {
                         None => String::from(""),
@@ -909,7 +1699,12 @@ fn choose_theme_shapes(
                         .as_ref()
                         .map(|s| s.to_string())
                         .unwrap_or_else(|| String::from(""));
-                    (chosen_theme, chosen_shapes, line_color)
+                    (
+                        chosen_theme,
+                        chosen_shapes,
+                        line_color,
+                        chosen_entry.filter.clone(),
+                    )
                 }
             }
         }
@@ -926,6 +1721,18 @@ impl ConfigLines {
             Tiling::Rhombus => (self.rho_width, &self.rho_color),
             Tiling::Pentagons(_) => (self.pen_width, &self.pen_color),
             Tiling::Delaunay => (self.del_width, &self.del_color),
+            Tiling::Squares => (self.squ_width, &self.squ_color),
+            Tiling::Bricks => (self.bri_width, &self.bri_color),
+            Tiling::Herringbone => (self.her_width, &self.her_color),
+            Tiling::Basketweave => (self.bas_width, &self.bas_color),
+            Tiling::OctagonsAndSquares => (self.oct_and_squ_width, &self.oct_and_squ_color),
+            Tiling::DodecagonsHexagonsAndSquares => {
+                (self.dod_hex_and_squ_width, &self.dod_hex_and_squ_color)
+            }
+            Tiling::DodecagonsAndTriangles => (self.dod_and_tri_width, &self.dod_and_tri_color),
+            Tiling::ElongatedTriangles => (self.elo_tri_width, &self.elo_tri_color),
+            Tiling::SnubSquares => (self.snub_squ_width, &self.snub_squ_color),
+            Tiling::SnubHexagons => (self.snub_hex_width, &self.snub_hex_color),
         };
         (
             w.unwrap_or_else(|| self.width.unwrap_or(LINE_WIDTH)),
@@ -944,6 +1751,239 @@ impl ConfigLines {
     }
 }
 
+impl ConfigFilter {
+    fn get_settings(&self, colors: &HashMap<String, Color>, verbose: Verbosity) -> Filter {
+        let duotone = match &self.duotone {
+            None => None,
+            Some(v) => match &v[..] {
+                [dark, light] => match (
+                    color_from_value(&Value::String(dark.to_string()), colors),
+                    color_from_value(&Value::String(light.to_string()), colors),
+                ) {
+                    (Ok(dark), Ok(light)) => Some((dark, light)),
+                    (Err(e), _) | (_, Err(e)) => {
+                        if verbose.warn {
+                            println!("{}", e);
+                        }
+                        None
+                    }
+                },
+                _ => {
+                    if verbose.warn {
+                        println!("Invalid duotone. Expected an array of two colors.");
+                    }
+                    None
+                }
+            },
+        };
+        Filter {
+            temperature: self.temperature,
+            hue: self.hue,
+            saturation: self.saturation,
+            brightness: self.brightness,
+            contrast: self.contrast,
+            grayscale: self.grayscale,
+            sepia: self.sepia,
+            duotone,
+        }
+    }
+}
+
+impl ConfigPatterns {
+    /// Sides, star likeliness, rotation, sizes and size distribution of free polygons
+    fn get_polygons(
+        &self,
+        verbose: Verbosity,
+    ) -> ((usize, usize), f64, isize, (f64, f64), SizeDistribution) {
+        let sides = match self.sides_polygons.as_deref() {
+            None => SIDES_POLYGONS,
+            Some(&[n]) => (n.max(3), n.max(3)),
+            Some(&[a, b]) => (a.min(b).max(3), a.max(b).max(3)),
+            Some(s) => {
+                if verbose.warn {
+                    println!(
+                        "Invalid sides_polygons {:?}. Expected one or two numbers.",
+                        s
+                    );
+                }
+                SIDES_POLYGONS
+            }
+        };
+        let size = match self.size_polygons.as_deref() {
+            None => SIZE_POLYGONS,
+            Some(&[s]) => (s, s),
+            Some(&[a, b]) => (a.min(b), a.max(b)),
+            Some(s) => {
+                if verbose.warn {
+                    println!(
+                        "Invalid size_polygons {:?}. Expected one or two numbers.",
+                        s
+                    );
+                }
+                SIZE_POLYGONS
+            }
+        };
+        let distribution = match self.distribution_polygons.as_deref() {
+            None => SizeDistribution::Linear,
+            Some(d) => SizeDistribution::from(d).unwrap_or_else(|| {
+                if verbose.warn {
+                    println!(
+                        "Unknown size distribution '{}', use one of 'linear', 'uniform', 'power'",
+                        d
+                    );
+                }
+                SizeDistribution::Linear
+            }),
+        };
+        (
+            sides,
+            self.star_polygons.unwrap_or(STAR_POLYGONS).clamp(0., 1.),
+            self.rotation_polygons.unwrap_or(ROTATION_POLYGONS).max(0),
+            size,
+            distribution,
+        )
+    }
+
+    /// Families, sizes, petals and band width of curves
+    fn get_curves(
+        &self,
+        verbose: Verbosity,
+    ) -> (Vec<CurveFamily>, (f64, f64), (usize, usize), f64) {
+        let families = self
+            .families_curves
+            .iter()
+            .flatten()
+            .filter_map(|s| {
+                let family = CurveFamily::from(s);
+                if family.is_none() && verbose.warn {
+                    println!(
+                        "Unknown curve '{}', use one of 'rose', 'lissajous', 'superformula'",
+                        s
+                    );
+                }
+                family
+            })
+            .collect::<Vec<_>>();
+        let size = match self.size_curves.as_deref() {
+            None => SIZE_CURVES,
+            Some(&[s]) => (s, s),
+            Some(&[a, b]) => (a.min(b), a.max(b)),
+            Some(s) => {
+                if verbose.warn {
+                    println!("Invalid size_curves {:?}. Expected one or two numbers.", s);
+                }
+                SIZE_CURVES
+            }
+        };
+        let petals = match self.petals_curves.as_deref() {
+            None => PETALS_CURVES,
+            Some(&[n]) => (n.max(1), n.max(1)),
+            Some(&[a, b]) => (a.min(b).max(1), a.max(b).max(1)),
+            Some(s) => {
+                if verbose.warn {
+                    println!(
+                        "Invalid petals_curves {:?}. Expected one or two numbers.",
+                        s
+                    );
+                }
+                PETALS_CURVES
+            }
+        };
+        (
+            families,
+            size,
+            petals,
+            self.width_curves.unwrap_or(WIDTH_CURVES),
+        )
+    }
+
+    /// Rule and number of steps of the cellular automaton
+    fn get_automaton(&self, verbose: Verbosity) -> (Rule, usize) {
+        let rule = match self.rule_automaton.as_deref() {
+            None | Some("majority") => Rule::Majority,
+            Some("life") => Rule::Life {
+                birth: self
+                    .birth_automaton
+                    .clone()
+                    .unwrap_or_else(|| BIRTH_AUTOMATON.to_vec()),
+                survive: self
+                    .survive_automaton
+                    .clone()
+                    .unwrap_or_else(|| SURVIVE_AUTOMATON.to_vec()),
+            },
+            Some("cyclic") => Rule::Cyclic {
+                threshold: self.threshold_automaton.unwrap_or(THRESHOLD_AUTOMATON),
+            },
+            Some(r) => {
+                if verbose.warn {
+                    println!(
+                        "Unknown rule '{}', use one of 'life', 'majority', 'cyclic'",
+                        r
+                    );
+                }
+                Rule::Majority
+            }
+        };
+        (rule, self.steps_automaton.unwrap_or(STEPS_AUTOMATON))
+    }
+}
+
+impl ConfigBlend {
+    fn get_settings(&self, pattern: Pattern, verbose: Verbosity) -> Blend {
+        let mode_from = |s: &str| {
+            BlendMode::from(s).unwrap_or_else(|| {
+                if verbose.warn {
+                    println!(
+                        "Unknown blend mode '{}', use one of 'normal', 'multiply', 'screen', 'overlay', 'average'",
+                        s
+                    );
+                }
+                BlendMode::Normal
+            })
+        };
+        let mut blend = Blend {
+            mode: self
+                .mode
+                .as_deref()
+                .map(mode_from)
+                .unwrap_or(BlendMode::Normal),
+            opacity: self.opacity.unwrap_or(BLEND_OPACITY),
+        };
+        for (name, val) in self.patterns.iter().flatten() {
+            let mut patterns = Chooser::default();
+            if !try_add_shape(name, BASE_WEIGHT, &mut Chooser::default(), &mut patterns) {
+                if verbose.warn {
+                    println!("{} is not recognized as a shape", name);
+                }
+                continue;
+            }
+            if !patterns.extract().iter().any(|(p, _)| *p == pattern) {
+                continue;
+            }
+            match val {
+                Value::String(s) => blend.mode = mode_from(s),
+                Value::Table(t) => {
+                    if let Some(Value::String(s)) = t.get("mode") {
+                        blend.mode = mode_from(s);
+                    }
+                    match t.get("opacity") {
+                        Some(Value::Float(f)) => blend.opacity = *f,
+                        Some(Value::Integer(n)) => blend.opacity = *n as f64,
+                        _ => (),
+                    }
+                }
+                _ => {
+                    if verbose.warn {
+                        println!("Invalid blend for '{}': {}", name, val);
+                    }
+                }
+            }
+        }
+        blend.opacity = blend.opacity.clamp(0., 1.);
+        blend
+    }
+}
+
 const DEVIATION: usize = 20;
 const DISTANCE: usize = 40;
 const SIZE: f64 = 15.;
@@ -951,13 +1991,24 @@ const WIDTH: usize = 1000;
 const HEIGHT: usize = 600;
 const NB_FREE_CIRCLES: usize = 10;
 const NB_FREE_TRIANGLES: usize = 15;
+const NB_FREE_POLYGONS: usize = 15;
 const NB_FREE_STRIPES: usize = 7;
 const NB_PARALLEL_STRIPES: usize = 15;
 const NB_CONCENTRIC_CIRCLES: usize = 5;
+const NB_RADIAL_RAYS: usize = 16;
+const NB_ARCS: usize = 12;
 const NB_CROSSED_STRIPES: usize = 10;
 const NB_FREE_SPIRALS: usize = 3;
+const NB_CURVES: usize = 6;
 const NB_PARALLEL_WAVES: usize = 15;
 const NB_PARALLEL_SAWTEETH: usize = 15;
+const NB_VORONOI: usize = 20;
+const NB_CONTOURS: usize = 8;
+const NB_FLOW_FIELD: usize = 5;
+const NB_FRACTAL: usize = 8;
+const NB_LINEAR_GRADIENT: usize = 4;
+const NB_RADIAL_GRADIENT: usize = 4;
+const NB_AUTOMATON: usize = 3;
 const VAR_PARALLEL_STRIPES: usize = 15;
 const VAR_CROSSED_STRIPES: usize = 10;
 const WIDTH_SPIRAL: f64 = 0.3;
@@ -965,6 +2016,38 @@ const WIDTH_STRIPE: f64 = 0.1;
 const WIDTH_WAVE: f64 = 0.3;
 const WIDTH_SAWTOOTH: f64 = 0.3;
 const TIGHTNESS_SPIRAL: f64 = 0.5;
+const WEIGHT_VORONOI: f64 = 0.;
+const SCALE_CONTOURS: f64 = 0.5;
+const OCTAVES_CONTOURS: usize = 4;
+const SIDES_POLYGONS: (usize, usize) = (3, 8);
+const STAR_POLYGONS: f64 = 0.3;
+const ROTATION_POLYGONS: isize = 360;
+const SIZE_POLYGONS: (f64, f64) = (0.05, 0.35);
+const CENTERS_ARCS: usize = 2;
+const GAP_ARCS: f64 = 0.3;
+const WIDTH_ARCS: f64 = 0.8;
+const SIZE_CURVES: (f64, f64) = (0.1, 0.4);
+const PETALS_CURVES: (usize, usize) = (2, 7);
+const WIDTH_CURVES: f64 = 0.03;
+const SCALE_FLOW: f64 = 0.6;
+const WIDTH_FLOW: f64 = 0.05;
+const ITERATIONS_FRACTAL: usize = 100;
+const STEPS_AUTOMATON: usize = 10;
+const THRESHOLD_AUTOMATON: usize = 1;
+const BIRTH_AUTOMATON: [usize; 1] = [3];
+const SURVIVE_AUTOMATON: [usize; 2] = [2, 3];
 const NB_DELAUNAY: usize = 1000;
+const RATIO_BRICKS: f64 = 2.;
+const OFFSET_BRICKS: f64 = 0.5;
+const NOISE_SCALE: f64 = 200.;
+const NOISE_OCTAVES: usize = 3;
+const LIGHT_ANGLE: isize = 135;
+const LIGHT_STRENGTH: f64 = 0.3;
+const CLUSTER_SIZE: f64 = 3.;
+const BLEND_OPACITY: f64 = 1.;
+const COVERAGE_SAMPLES: usize = 9;
+const CSG_RATIO: f64 = 0.6;
+const FEATHER_WIDTH: f64 = 40.;
+const BACKGROUND_COLORS: usize = 3;
 const LINE_WIDTH: f64 = 1.0;
 const LINE_COLOR: Color = Color(0, 0, 0);
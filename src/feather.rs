@@ -0,0 +1,71 @@
+use crate::prelude::*;
+use crate::scene::Contains;
+use rand::{rngs::StdRng, Rng};
+
+/// How the soft edge of an item is rendered
+#[derive(Debug, Clone, Copy)]
+pub enum FeatherMode {
+    /// Mix the color of the item with what is below it
+    Blend,
+    /// Randomly show either the item or what is below it
+    Dither,
+}
+
+impl FeatherMode {
+    pub fn from(s: &str) -> Option<Self> {
+        match s {
+            "blend" => Some(FeatherMode::Blend),
+            "dither" => Some(FeatherMode::Dither),
+            _ => None,
+        }
+    }
+}
+
+/// Soft edges: items that know the distance to their edge
+/// fade into the background over a band of the given width
+#[derive(Debug, Clone, Copy)]
+pub struct Feather {
+    pub mode: FeatherMode,
+    /// Width of the band, centered on the edge
+    pub width: f64,
+}
+
+impl Feather {
+    /// How much of an item shows at a point, between 0 and 1.
+    /// Items with hard edges are either fully visible or not at all.
+    pub fn opacity(&self, item: &dyn Contains, p: Pos, rng: &mut StdRng) -> f64 {
+        let alpha = match item.edge(p) {
+            Some(d) if self.width > 0. => {
+                let t = (d / self.width + 0.5).clamp(0., 1.);
+                t * t * (3. - 2. * t)
+            }
+            Some(d) => opaque(d > 0.),
+            None => opaque(item.hit(p)),
+        };
+        match self.mode {
+            FeatherMode::Blend => alpha,
+            FeatherMode::Dither if alpha <= 0. || alpha >= 1. => alpha,
+            FeatherMode::Dither => opaque(rng.gen::<f64>() < alpha),
+        }
+    }
+
+    /// Distance beyond its bounding box up to which an item can be visible
+    pub fn margin(&self) -> f64 {
+        self.width / 2.
+    }
+}
+
+fn opaque(visible: bool) -> f64 {
+    if visible {
+        1.
+    } else {
+        0.
+    }
+}
+
+/// Paint `above` over `below` with the given opacity
+pub fn mix(below: Color, above: Color, alpha: f64) -> Color {
+    let (b, a) = (below.to_f64(), above.to_f64());
+    let m = |b: f64, a: f64| b + (a - b) * alpha;
+    Color::from_f64((m(b.0, a.0), m(b.1, a.1), m(b.2, a.2)))
+}
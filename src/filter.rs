@@ -0,0 +1,88 @@
+use crate::prelude::*;
+
+/// Color adjustments applied to the whole image once each tile has been colored.
+/// Unset fields leave the image untouched.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Filter {
+    /// Positive is warmer (more red), negative is colder (more blue), within [-100; 100]
+    pub temperature: Option<f64>,
+    /// Rotation of the hue in degrees
+    pub hue: Option<f64>,
+    /// 0 for gray, 1 for unchanged, more for vivid colors
+    pub saturation: Option<f64>,
+    /// 0 for black, 1 for unchanged
+    pub brightness: Option<f64>,
+    /// 0 for uniform gray, 1 for unchanged
+    pub contrast: Option<f64>,
+    pub grayscale: Option<bool>,
+    pub sepia: Option<bool>,
+    /// Map dark colors to the first, light colors to the second
+    pub duotone: Option<(Color, Color)>,
+}
+
+impl Filter {
+    /// Fields set in `self` take precedence over those of `base`
+    pub fn over(self, base: Self) -> Self {
+        Self {
+            temperature: self.temperature.or(base.temperature),
+            hue: self.hue.or(base.hue),
+            saturation: self.saturation.or(base.saturation),
+            brightness: self.brightness.or(base.brightness),
+            contrast: self.contrast.or(base.contrast),
+            grayscale: self.grayscale.or(base.grayscale),
+            sepia: self.sepia.or(base.sepia),
+            duotone: self.duotone.or(base.duotone),
+        }
+    }
+
+    pub fn apply(&self, mut c: Color) -> Color {
+        if let Some(t) = self.temperature {
+            let (r, g, b) = c.to_f64();
+            c = Color::from_f64((
+                r * (1. + t / 100.),
+                g * (1. + t / 400.),
+                b * (1. - t / 100.),
+            ));
+        }
+        if let Some(h) = self.hue {
+            let (hue, s, l) = c.to_hsl();
+            c = Color::from_hsl((hue + h, s, l));
+        }
+        if let Some(s) = self.saturation {
+            let (r, g, b) = c.to_f64();
+            let y = c.luma();
+            c = Color::from_f64((y + (r - y) * s, y + (g - y) * s, y + (b - y) * s));
+        }
+        if let Some(k) = self.brightness {
+            let (r, g, b) = c.to_f64();
+            c = Color::from_f64((r * k, g * k, b * k));
+        }
+        if let Some(k) = self.contrast {
+            let (r, g, b) = c.to_f64();
+            let stretch = |x: f64| (x - 128.) * k + 128.;
+            c = Color::from_f64((stretch(r), stretch(g), stretch(b)));
+        }
+        if self.grayscale == Some(true) {
+            let y = c.luma();
+            c = Color::from_f64((y, y, y));
+        }
+        if self.sepia == Some(true) {
+            let (r, g, b) = c.to_f64();
+            c = Color::from_f64((
+                0.393 * r + 0.769 * g + 0.189 * b,
+                0.349 * r + 0.686 * g + 0.168 * b,
+                0.272 * r + 0.534 * g + 0.131 * b,
+            ));
+        }
+        if let Some((dark, light)) = self.duotone {
+            let t = c.luma() / 255.;
+            let (d, l) = (dark.to_f64(), light.to_f64());
+            c = Color::from_f64((
+                d.0 + (l.0 - d.0) * t,
+                d.1 + (l.1 - d.1) * t,
+                d.2 + (l.2 - d.2) * t,
+            ));
+        }
+        c
+    }
+}
@@ -1,10 +1,19 @@
+pub mod blend;
 pub mod cfg;
 pub mod chooser;
 pub mod color;
+pub mod coverage;
+pub mod csg;
 pub mod deserializer;
+pub mod feather;
+pub mod filter;
 pub mod frame;
+pub mod lighting;
 pub mod log;
+pub mod noise;
 pub mod paint;
+pub mod palette;
+pub mod parallel;
 pub mod pos;
 pub mod salt;
 pub mod scene;
@@ -22,11 +31,12 @@ pub mod prelude {
     pub use pos::{radians, Pos};
     pub use salt::{Salt, SaltItem};
 
+    use serde_derive::{Deserialize, Serialize};
     use std::collections::HashMap;
     pub type ColorList = HashMap<String, Color>;
     pub type ThemeList = HashMap<String, Chooser<ThemeItem>>;
 
-    #[derive(Clone, Debug)]
+    #[derive(Clone, Debug, Serialize, Deserialize)]
     pub struct ThemeItem(pub Color, pub Option<usize>, pub Option<usize>, pub Salt);
 }
 
@@ -0,0 +1,45 @@
+use crate::prelude::*;
+use crate::svg::Path;
+
+/// A directional light source giving tiles a relief effect
+#[derive(Debug, Clone, Copy)]
+pub struct Lighting {
+    /// Direction the light comes from, in degrees counterclockwise from the right of the screen
+    pub angle: isize,
+    /// 0 for flat colors, 1 for very pronounced shadows
+    pub strength: f64,
+}
+
+/// How steep tiles appear when their facing is taken into account
+const BEVEL: f64 = 0.5;
+
+impl Lighting {
+    /// Brighten or darken a color depending on the slope of the surface it is painted on.
+    /// The slope is the gradient of a virtual height map: a flat surface has a zero slope.
+    pub fn shade(&self, c: Color, slope: Pos) -> Color {
+        // Screen coordinates have the y axis pointing downwards
+        let Pos(lx, ly) = Pos::polar(-self.angle, 1.);
+        // Light is at 45° elevation, surface normal is (-gx, -gy, 1) normalized
+        let flat = 0.5_f64.sqrt();
+        let lit =
+            (-slope.0 * lx * flat - slope.1 * ly * flat + flat) / (1. + slope.dot_self()).sqrt();
+        let factor = 1. + self.strength * (lit / flat - 1.);
+        if factor >= 1. {
+            c.meanpoint(
+                Color(255, 255, 255),
+                (100. * (2. - factor).max(0.)).round() as usize,
+            )
+        } else {
+            c.meanpoint(Color(0, 0, 0), (100. * factor.max(0.)).round() as usize)
+        }
+    }
+
+    /// Slope of a tile according to its orientation: tiles are beveled towards their first vertex,
+    /// so that differently rotated tiles catch the light differently
+    pub fn facing(center: Pos, path: &Path) -> Pos {
+        match path.data.0.first() {
+            Some(&v) if v != center => (v - center).unit() * BEVEL,
+            _ => Pos::zero(),
+        }
+    }
+}
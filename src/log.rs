@@ -1,14 +1,71 @@
+use crate::blend::{Blend, BlendMode, Blended};
+use crate::csg::{Combined, CsgOp, Matrix, Transformed};
+use crate::noise::{Noise, NoiseKind};
 use crate::prelude::*;
+use crate::salt::{Cluster, ClusterMode};
 use crate::scene::*;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt;
 use std::fs::File;
 use std::io::{Read, Write};
-use std::rc::Rc;
+use std::sync::Arc;
+
+thread_local! {
+    /// Identifiers of the shared data already written to the log being saved, by address
+    static WRITTEN: RefCell<HashMap<usize, usize>> = RefCell::new(HashMap::new());
+    /// Shared data already read from the log being loaded, by identifier
+    static READ: RefCell<Vec<Arc<dyn Any + Send + Sync>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Data shared by several items is written in full where it first appears,
+/// and only as its identifier afterwards
+fn write_shared<T: fmt::Display>(f: &mut fmt::Formatter, shared: &Arc<Shared<T>>) -> fmt::Result {
+    let address = Arc::as_ptr(shared) as usize;
+    let (id, first) = WRITTEN.with(|written| {
+        let mut written = written.borrow_mut();
+        let next = written.len();
+        match written.get(&address) {
+            Some(&id) => (id, false),
+            None => {
+                written.insert(address, next);
+                (next, true)
+            }
+        }
+    });
+    write!(f, "{} ", id)?;
+    if first {
+        write!(f, "{} ", shared.inner)?;
+    }
+    Ok(())
+}
+
+fn restore_shared<'a, T>(items: &mut impl Iterator<Item = &'a str>) -> Arc<Shared<T>>
+where
+    T: Restore + Partition + 'static,
+{
+    let id = usize::restore(items);
+    let known = READ.with(|read| read.borrow().get(id).cloned());
+    match known {
+        Some(shared) => shared.downcast::<Shared<T>>().unwrap(),
+        None => {
+            let shared = Shared::new(T::restore(items));
+            READ.with(|read| {
+                let mut read = read.borrow_mut();
+                assert_eq!(id, read.len());
+                read.push(shared.clone());
+            });
+            shared
+        }
+    }
+}
 
 pub struct Logger {
     pub frame: Frame,
     pub bg: ColorItem,
-    pub objects: Vec<Rc<dyn Contains>>,
+    pub backdrop: Option<Gradient>,
+    pub objects: Vec<Arc<dyn Contains>>,
 }
 
 impl fmt::Display for Frame {
@@ -22,21 +79,44 @@ impl fmt::Display for ColorItem {
         write!(f, "{} {} {} ", self.shade.0, self.shade.1, self.shade.2)?;
         write!(f, "{} {} {} ", self.theme.0, self.theme.1, self.theme.2)?;
         write!(f, "{} ", self.salt)?;
-        write!(f, "{} {} #", self.deviation, self.distance)
+        write!(f, "{} {} ", self.deviation, self.distance)?;
+        match &self.noise {
+            None => write!(f, "- #"),
+            Some(noise) => write!(f, "{} #", noise),
+        }
+    }
+}
+
+impl fmt::Display for Noise {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {} {} {}",
+            self.kind, self.seed, self.scale, self.octaves
+        )
     }
 }
 
 impl fmt::Display for SaltItem {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{} {} {} ", self.color.0, self.color.1, self.color.2)?;
-        write!(f, "{} {} ", self.likeliness, self.variability)
+        write!(f, "{} {} ", self.likeliness, self.variability)?;
+        match &self.cluster {
+            None => write!(f, "- "),
+            Some(c) => write!(
+                f,
+                "{:?} {} {} {} {} {} ",
+                c.mode, c.size, c.noise, c.direction.0, c.direction.1, c.threshold
+            ),
+        }
     }
 }
 
 impl fmt::Display for Salt {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ", self.0.len())?;
         for item in self.0.iter() {
-            write!(f, "{} ", item)?;
+            write!(f, "{}", item)?;
         }
         write!(f, "#")
     }
@@ -73,6 +153,63 @@ impl fmt::Display for Triangle {
     }
 }
 
+impl fmt::Display for Polygon {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Polygon {} {} {} {} {} {} ",
+            self.center.0, self.center.1, self.radius, self.sides, self.step, self.rotation
+        )?;
+        write!(f, "{} #", self.color)
+    }
+}
+
+impl fmt::Display for Sector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Sector {} {} {} {} {} ",
+            self.center.0, self.center.1, self.start, self.width, self.twist
+        )?;
+        write!(f, "{} #", self.color)
+    }
+}
+
+impl fmt::Display for Annulus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Annulus {} {} {} {} {} {} ",
+            self.center.0, self.center.1, self.inner, self.outer, self.start, self.width
+        )?;
+        write!(f, "{} #", self.color)
+    }
+}
+
+impl fmt::Display for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Curve {} {} {} {} ",
+            self.center.0, self.center.1, self.size, self.rotation
+        )?;
+        match self.kind {
+            CurveKind::Rose { n, d } => write!(f, "Rose {} {} ", n, d)?,
+            CurveKind::Lissajous {
+                a,
+                b,
+                phase,
+                ratio,
+                width,
+            } => write!(f, "Lissajous {} {} {} {} {} ", a, b, phase, ratio, width)?,
+            CurveKind::Superformula { m, n1, n2, n3 } => {
+                write!(f, "Superformula {} {} {} {} ", m, n1, n2, n3)?
+            }
+        }
+        write!(f, "{} #", self.color)
+    }
+}
+
 impl fmt::Display for Spiral {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -95,6 +232,111 @@ impl fmt::Display for Stripe {
     }
 }
 
+impl fmt::Display for Sites {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} {}", self.metric, self.centers.len())?;
+        for (c, w) in self.centers.iter().zip(self.weights.iter()) {
+            write!(f, " {} {} {}", c.0, c.1, w)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Voronoi {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Voronoi ")?;
+        write_shared(f, &self.sites)?;
+        write!(f, "{} {} #", self.index, self.color)
+    }
+}
+
+impl fmt::Display for Contour {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Contour {} {} {} ", self.noise, self.level, self.terrace)?;
+        write!(f, "{} #", self.color)
+    }
+}
+
+impl fmt::Display for Streamlines {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} ", self.bands, self.width)?;
+        match &self.flow {
+            Flow::Noise(noise) => write!(f, "Noise {}", noise),
+            Flow::Vortices(v) => {
+                write!(f, "Vortices {}", v.len())?;
+                for (c, s) in v {
+                    write!(f, " {} {} {}", c.0, c.1, s)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Stream ")?;
+        write_shared(f, &self.field)?;
+        write!(f, "{} {} #", self.band, self.color)
+    }
+}
+
+impl fmt::Display for EscapeTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ", self.bands)?;
+        match self.julia {
+            None => write!(f, "- ")?,
+            Some(c) => write!(f, "{} {} ", c.0, c.1)?,
+        }
+        write!(
+            f,
+            "{} {} {} {} {} {}",
+            self.origin.0, self.origin.1, self.center.0, self.center.1, self.unit, self.iterations
+        )
+    }
+}
+
+impl fmt::Display for Escape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Escape ")?;
+        write_shared(f, &self.fractal)?;
+        write!(f, "{} {} #", self.band, self.color)
+    }
+}
+
+impl fmt::Display for Gradient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Gradient {:?} {} {} {} {} ",
+            self.shape, self.start.0, self.start.1, self.end.0, self.end.1
+        )?;
+        write!(f, "{} {} {} ", self.banded, self.jitter, self.colors.len())?;
+        for c in &self.colors {
+            write!(f, "{} ", c)?;
+        }
+        write!(f, "#")
+    }
+}
+
+impl fmt::Display for Automaton {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.cells.len())?;
+        for (p, s) in &self.cells {
+            write!(f, " {} {} {}", p.0, p.1, s)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Cell ")?;
+        write_shared(f, &self.automaton)?;
+        write!(f, "{} {} #", self.state, self.color)
+    }
+}
+
 impl fmt::Display for Wave {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -119,10 +361,37 @@ impl fmt::Display for Sawtooth {
     }
 }
 
+impl fmt::Display for Transformed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let m = self.matrix.0;
+        write!(f, "Transformed {} {} ", self.origin.0, self.origin.1)?;
+        write!(f, "{} {} {} {} ", m[0][0], m[0][1], m[1][0], m[1][1])?;
+        write!(f, "{} #", self.inner)
+    }
+}
+
+impl fmt::Display for Combined {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Combined {:?} {} {} #", self.op, self.a, self.b)
+    }
+}
+
+impl fmt::Display for Blended {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let b = &self.blend;
+        write!(f, "Blended {:?} {} {} #", b.mode, b.opacity, self.inner)
+    }
+}
+
 impl fmt::Display for Logger {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        WRITTEN.with(|written| written.borrow_mut().clear());
         write!(f, "{} ", self.frame)?;
         write!(f, "{} ", self.bg)?;
+        match &self.backdrop {
+            None => write!(f, "- ")?,
+            Some(g) => write!(f, "{} ", g)?,
+        }
         write!(f, "{} ", self.objects.len())?;
         for o in &self.objects {
             write!(f, "{} ", o)?;
@@ -156,6 +425,12 @@ impl Restore for usize {
     }
 }
 
+impl Restore for u64 {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        items.next().unwrap().parse::<Self>().unwrap()
+    }
+}
+
 impl Restore for f64 {
     fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
         items.next().unwrap().parse::<Self>().unwrap()
@@ -180,23 +455,102 @@ impl Restore for Color {
 
 impl Restore for Logger {
     fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        READ.with(|read| read.borrow_mut().clear());
         let frame = Frame::restore(items);
         let bg = ColorItem::restore(items);
+        let backdrop = match items.next().unwrap() {
+            "-" => None,
+            "Gradient" => Some(Gradient::restore(items)),
+            _ => panic!("Unknown background"),
+        };
         let len = items.next().unwrap().parse::<usize>().unwrap();
         let mut objects = Vec::new();
         for _ in 0..len {
-            objects.push(match items.next().unwrap() {
-                "Disc" => Rc::new(Disc::restore(items)) as Rc<dyn Contains>,
-                "HalfPlane" => Rc::new(HalfPlane::restore(items)) as Rc<dyn Contains>,
-                "Stripe" => Rc::new(Stripe::restore(items)) as Rc<dyn Contains>,
-                "Triangle" => Rc::new(Triangle::restore(items)) as Rc<dyn Contains>,
-                "Spiral" => Rc::new(Spiral::restore(items)) as Rc<dyn Contains>,
-                "Wave" => Rc::new(Wave::restore(items)) as Rc<dyn Contains>,
-                "Sawtooth" => Rc::new(Sawtooth::restore(items)) as Rc<dyn Contains>,
-                _ => panic!("Unknown item"),
-            });
+            objects.push(Arc::<dyn Contains>::restore(items));
+        }
+        Self {
+            frame,
+            bg,
+            backdrop,
+            objects,
+        }
+    }
+}
+
+impl Restore for Arc<dyn Contains> {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        match items.next().unwrap() {
+            "Disc" => Arc::new(Disc::restore(items)),
+            "HalfPlane" => Arc::new(HalfPlane::restore(items)),
+            "Stripe" => Arc::new(Stripe::restore(items)),
+            "Triangle" => Arc::new(Triangle::restore(items)),
+            "Polygon" => Arc::new(Polygon::restore(items)),
+            "Sector" => Arc::new(Sector::restore(items)),
+            "Annulus" => Arc::new(Annulus::restore(items)),
+            "Spiral" => Arc::new(Spiral::restore(items)),
+            "Curve" => Arc::new(Curve::restore(items)),
+            "Wave" => Arc::new(Wave::restore(items)),
+            "Sawtooth" => Arc::new(Sawtooth::restore(items)),
+            "Voronoi" => Arc::new(Voronoi::restore(items)),
+            "Contour" => Arc::new(Contour::restore(items)),
+            "Stream" => Arc::new(Stream::restore(items)),
+            "Escape" => Arc::new(Escape::restore(items)),
+            "Gradient" => Arc::new(Gradient::restore(items)),
+            "Cell" => Arc::new(Cell::restore(items)),
+            "Transformed" => Arc::new(Transformed::restore(items)),
+            "Combined" => Arc::new(Combined::restore(items)),
+            "Blended" => Arc::new(Blended::restore(items)),
+            _ => panic!("Unknown item"),
+        }
+    }
+}
+
+impl Restore for Transformed {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let origin = Pos::restore(items);
+        let m = [
+            [f64::restore(items), f64::restore(items)],
+            [f64::restore(items), f64::restore(items)],
+        ];
+        let inner = Arc::<dyn Contains>::restore(items);
+        assert_eq!(items.next().unwrap(), "#");
+        Transformed::new(inner, origin, Matrix(m))
+    }
+}
+
+impl Restore for Combined {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let op = match items.next().unwrap() {
+            "Union" => CsgOp::Union,
+            "Intersection" => CsgOp::Intersection,
+            "Difference" => CsgOp::Difference,
+            "Xor" => CsgOp::Xor,
+            _ => panic!("Unknown operation"),
+        };
+        let a = Arc::<dyn Contains>::restore(items);
+        let b = Arc::<dyn Contains>::restore(items);
+        assert_eq!(items.next().unwrap(), "#");
+        Self { op, a, b }
+    }
+}
+
+impl Restore for Blended {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let mode = match items.next().unwrap() {
+            "Normal" => BlendMode::Normal,
+            "Multiply" => BlendMode::Multiply,
+            "Screen" => BlendMode::Screen,
+            "Overlay" => BlendMode::Overlay,
+            "Average" => BlendMode::Average,
+            _ => panic!("Unknown blend mode"),
+        };
+        let opacity = f64::restore(items);
+        let inner = Arc::<dyn Contains>::restore(items);
+        assert_eq!(items.next().unwrap(), "#");
+        Self {
+            inner,
+            blend: Blend { mode, opacity },
         }
-        Self { frame, bg, objects }
     }
 }
 
@@ -228,10 +582,12 @@ impl Restore for SaltItem {
         let color = Color::restore(items);
         let likeliness = f64::restore(items);
         let variability = usize::restore(items);
+        let cluster = Option::<Cluster>::restore(items);
         Self {
             color,
             likeliness,
             variability,
+            cluster,
         }
     }
 }
@@ -243,17 +599,74 @@ impl Restore for ColorItem {
         let salt = Salt::restore(items);
         let deviation = usize::restore(items);
         let distance = usize::restore(items);
-        assert_eq!(items.next().unwrap(), "#");
+        // Logs written before colors had noise end right after the distance
+        let noise = match items.next().unwrap() {
+            "#" => None,
+            kind => {
+                let noise = restore_noise(kind, items);
+                assert_eq!(items.next().unwrap(), "#");
+                noise
+            }
+        };
         Self {
             shade,
             theme,
             deviation,
             distance,
             salt,
+            noise,
         }
     }
 }
 
+impl Restore for Option<Cluster> {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let mode = match items.next().unwrap() {
+            "-" => return None,
+            "Blob" => ClusterMode::Blob,
+            "Streak" => ClusterMode::Streak,
+            _ => panic!("Unknown cluster"),
+        };
+        let size = f64::restore(items);
+        let noise = Option::<Noise>::restore(items).unwrap();
+        let direction = Pos::restore(items);
+        let threshold = f64::restore(items);
+        Some(Cluster {
+            mode,
+            size,
+            noise,
+            direction,
+            threshold,
+        })
+    }
+}
+
+impl Restore for Option<Noise> {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        restore_noise(items.next().unwrap(), items)
+    }
+}
+
+/// Noise whose kind has already been read
+fn restore_noise<'a>(kind: &str, items: &mut impl Iterator<Item = &'a str>) -> Option<Noise> {
+    let kind = match kind {
+        "-" => return None,
+        "Value" => NoiseKind::Value,
+        "Perlin" => NoiseKind::Perlin,
+        "Simplex" => NoiseKind::Simplex,
+        _ => panic!("Unknown noise"),
+    };
+    let seed = u64::restore(items);
+    let scale = f64::restore(items);
+    let octaves = usize::restore(items);
+    Some(Noise {
+        kind,
+        seed,
+        scale,
+        octaves,
+    })
+}
+
 impl Restore for Disc {
     fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
         let center = Pos::restore(items);
@@ -307,6 +720,88 @@ impl Restore for Triangle {
     }
 }
 
+impl Restore for Polygon {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let center = Pos::restore(items);
+        let radius = f64::restore(items);
+        let sides = usize::restore(items);
+        let step = usize::restore(items);
+        let rotation = items.next().unwrap().parse::<isize>().unwrap();
+        let color = ColorItem::restore(items);
+        assert_eq!(items.next().unwrap(), "#");
+        Polygon::new(center, radius, sides, step, rotation, color)
+    }
+}
+
+impl Restore for Sector {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let center = Pos::restore(items);
+        let start = f64::restore(items);
+        let width = f64::restore(items);
+        let twist = f64::restore(items);
+        let color = ColorItem::restore(items);
+        assert_eq!(items.next().unwrap(), "#");
+        Self {
+            center,
+            start,
+            width,
+            twist,
+            color,
+        }
+    }
+}
+
+impl Restore for Annulus {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let center = Pos::restore(items);
+        let inner = f64::restore(items);
+        let outer = f64::restore(items);
+        let start = f64::restore(items);
+        let width = f64::restore(items);
+        let color = ColorItem::restore(items);
+        assert_eq!(items.next().unwrap(), "#");
+        Self {
+            center,
+            inner,
+            outer,
+            start,
+            width,
+            color,
+        }
+    }
+}
+
+impl Restore for Curve {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let center = Pos::restore(items);
+        let size = f64::restore(items);
+        let rotation = items.next().unwrap().parse::<isize>().unwrap();
+        let kind = match items.next().unwrap() {
+            "Rose" => CurveKind::Rose {
+                n: usize::restore(items),
+                d: usize::restore(items),
+            },
+            "Lissajous" => CurveKind::Lissajous {
+                a: usize::restore(items),
+                b: usize::restore(items),
+                phase: f64::restore(items),
+                ratio: f64::restore(items),
+                width: f64::restore(items),
+            },
+            "Superformula" => CurveKind::Superformula {
+                m: f64::restore(items),
+                n1: f64::restore(items),
+                n2: f64::restore(items),
+                n3: f64::restore(items),
+            },
+            _ => panic!("Unknown curve"),
+        };
+        let color = ColorItem::restore(items);
+        assert_eq!(items.next().unwrap(), "#");
+        Curve::new(center, size, rotation, kind, color)
+    }
+}
+
 impl Restore for Spiral {
     fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
         let center = Pos::restore(items);
@@ -323,6 +818,174 @@ impl Restore for Spiral {
     }
 }
 
+impl Restore for Sites {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let metric = match items.next().unwrap() {
+            "Euclidean" => Metric::Euclidean,
+            "Manhattan" => Metric::Manhattan,
+            "Chebyshev" => Metric::Chebyshev,
+            _ => panic!("Unknown metric"),
+        };
+        let nb = usize::restore(items);
+        let mut centers = Vec::new();
+        let mut weights = Vec::new();
+        for _ in 0..nb {
+            centers.push(Pos::restore(items));
+            weights.push(f64::restore(items));
+        }
+        Self {
+            metric,
+            centers,
+            weights,
+        }
+    }
+}
+
+impl Restore for Voronoi {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let sites = restore_shared::<Sites>(items);
+        let index = usize::restore(items);
+        let color = ColorItem::restore(items);
+        assert_eq!(items.next().unwrap(), "#");
+        Self {
+            sites,
+            index,
+            color,
+        }
+    }
+}
+
+impl Restore for Contour {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let noise = Option::<Noise>::restore(items).unwrap();
+        let level = f64::restore(items);
+        let terrace = items.next().unwrap().parse::<bool>().unwrap();
+        let color = ColorItem::restore(items);
+        assert_eq!(items.next().unwrap(), "#");
+        Self {
+            noise,
+            level,
+            terrace,
+            color,
+        }
+    }
+}
+
+impl Restore for Streamlines {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let bands = usize::restore(items);
+        let width = f64::restore(items);
+        let flow = match items.next().unwrap() {
+            "Noise" => Flow::Noise(Option::<Noise>::restore(items).unwrap()),
+            "Vortices" => {
+                let nb = usize::restore(items);
+                Flow::Vortices(
+                    (0..nb)
+                        .map(|_| (Pos::restore(items), f64::restore(items)))
+                        .collect(),
+                )
+            }
+            _ => panic!("Unknown flow"),
+        };
+        Self { flow, bands, width }
+    }
+}
+
+impl Restore for Stream {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let field = restore_shared::<Streamlines>(items);
+        let band = usize::restore(items);
+        let color = ColorItem::restore(items);
+        assert_eq!(items.next().unwrap(), "#");
+        Self { field, band, color }
+    }
+}
+
+impl Restore for Gradient {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let shape = match items.next().unwrap() {
+            "Linear" => GradientShape::Linear,
+            "Radial" => GradientShape::Radial,
+            _ => panic!("Unknown gradient"),
+        };
+        let start = Pos::restore(items);
+        let end = Pos::restore(items);
+        let banded = items.next().unwrap().parse::<bool>().unwrap();
+        let jitter = f64::restore(items);
+        let len = usize::restore(items);
+        let colors = (0..len).map(|_| ColorItem::restore(items)).collect();
+        assert_eq!(items.next().unwrap(), "#");
+        Self {
+            shape,
+            start,
+            end,
+            banded,
+            jitter,
+            colors,
+        }
+    }
+}
+
+impl Restore for Automaton {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let len = usize::restore(items);
+        let cells = (0..len)
+            .map(|_| (Pos::restore(items), usize::restore(items)))
+            .collect();
+        Automaton::new(cells)
+    }
+}
+
+impl Restore for Cell {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let automaton = restore_shared::<Automaton>(items);
+        let state = usize::restore(items);
+        let color = ColorItem::restore(items);
+        assert_eq!(items.next().unwrap(), "#");
+        Self {
+            automaton,
+            state,
+            color,
+        }
+    }
+}
+
+impl Restore for EscapeTime {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let bands = usize::restore(items);
+        let julia = match items.next().unwrap() {
+            "-" => None,
+            x => Some(Pos(x.parse::<f64>().unwrap(), f64::restore(items))),
+        };
+        let origin = Pos::restore(items);
+        let center = Pos::restore(items);
+        let unit = f64::restore(items);
+        let iterations = usize::restore(items);
+        Self {
+            julia,
+            origin,
+            center,
+            unit,
+            iterations,
+            bands,
+        }
+    }
+}
+
+impl Restore for Escape {
+    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
+        let fractal = restore_shared::<EscapeTime>(items);
+        let band = usize::restore(items);
+        let color = ColorItem::restore(items);
+        assert_eq!(items.next().unwrap(), "#");
+        Self {
+            fractal,
+            band,
+            color,
+        }
+    }
+}
+
 impl Restore for Wave {
     fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
         let limit = Pos::restore(items);
@@ -1,11 +1,16 @@
 use chrono::{Local, Timelike};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use std::env;
-use std::fs::File;
 use std::fs::rename;
+use std::fs::File;
 use std::io::prelude::*;
 use std::process::exit;
 use wallrnd::deserializer::MetaConfig;
+use wallrnd::filter::Filter;
+use wallrnd::lighting::Lighting;
 use wallrnd::log::Logger;
+use wallrnd::palette::Palette;
+use wallrnd::parallel;
 use wallrnd::prelude::*;
 use wallrnd::scene::Scene;
 use wallrnd::svg::*;
@@ -32,7 +37,7 @@ fn main() {
         }
     }
 
-    if args.init != "" {
+    if !args.init.is_empty() {
         if verbose.prog {
             println!("Initializing configuration file");
         }
@@ -60,7 +65,14 @@ fn main() {
     if verbose.prog {
         println!("Creating random number generator");
     }
-    let mut rng = rand::thread_rng();
+    let seed = args.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    if verbose.info {
+        println!("Using seed: {}", seed);
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    if let Some(n) = args.threads {
+        parallel::set_threads(n);
+    }
     if verbose.prog {
         println!("Attempting to open configuration file");
     }
@@ -86,26 +98,48 @@ fn main() {
     if let Some(h) = args.height {
         cfg.frame.h = h;
     }
+    cfg.filter = args.filter.over(cfg.filter);
+
+    // The frame of a saved scene must be known before tiles are created
+    let loader = if args.load.is_empty() {
+        None
+    } else {
+        Some(Logger::load(&args.load))
+    };
+    if let Some(loader) = &loader {
+        cfg.frame = loader.frame;
+    }
+
+    // Some patterns need the tiles, so they are created first
+    if verbose.prog {
+        println!("Creating tiling");
+    }
+    let tiling = cfg.make_tiling(&mut rng);
 
     if verbose.prog {
         println!("Building scene");
     }
-    let mut scene = Scene::new(&cfg, &mut rng, verbose);
-    let stroke = cfg.line_color;
+    let mut scene = Scene::new(&cfg, &tiling, &mut rng, verbose);
+    let mut stroke = cfg.filter.apply(cfg.line_color);
     let stroke_width = cfg.line_width;
     let stroke_like_fill = stroke_width < 0.0001;
 
-    if args.load != "" {
-        let loader = Logger::load(&args.load);
-        let Logger { bg, objects, frame } = loader;
+    if let Some(Logger {
+        bg,
+        backdrop,
+        objects,
+        frame: _,
+    }) = loader
+    {
         scene.bg = bg;
-        scene.items = objects;
-        cfg.frame = frame;
+        scene.backdrop = backdrop;
+        scene.set_items(objects);
     }
 
-    if args.log != "" {
+    if !args.log.is_empty() {
         let logger = Logger {
             bg: scene.bg.clone(),
+            backdrop: scene.backdrop.clone(),
             objects: scene.items.clone(),
             frame: cfg.frame,
         };
@@ -118,19 +152,112 @@ fn main() {
 
     // Generate document
     if verbose.prog {
-        println!("Creating tiling");
+        println!("Coloring tiles");
+    }
+    // Each tile gets its own random stream so that the result does not depend on the number of threads
+    let base: u64 = rng.gen();
+    let fills = parallel::map(&tiling, |i, (pos, elem)| {
+        let mut rng = StdRng::seed_from_u64(base.wrapping_add(i as u64));
+        let (fill, slope, pieces) = match &cfg.coverage {
+            None => {
+                let (fill, slope) = scene.color_relief(*pos, &mut rng);
+                (fill, slope, Vec::new())
+            }
+            Some(coverage) => coverage.color(&scene, *pos, elem, &mut rng),
+        };
+        let shade = |c, slope| match &cfg.lighting {
+            None => c,
+            Some(light) => light.shade(c, slope + Lighting::facing(*pos, elem)),
+        };
+        let fill = shade(fill, slope);
+        let pieces = pieces
+            .into_iter()
+            .map(|(piece, c, slope)| (piece, cfg.filter.apply(shade(c, slope))))
+            .collect::<Vec<_>>();
+        (cfg.filter.apply(fill), pieces)
+    });
+    // Parts of tiles cut along the edges of items are painted over their tile
+    let tiles = tiling
+        .into_iter()
+        .zip(fills)
+        .map(|((pos, elem), (fill, pieces))| (pos, elem, fill, pieces))
+        .collect::<Vec<_>>();
+    // Every color painted, along with where it is painted
+    let mut fills = Vec::new();
+    for (pos, _, fill, pieces) in &tiles {
+        fills.push((*pos, *fill));
+        for (piece, c) in pieces {
+            let pts = &piece.data.0;
+            let center = pts.iter().fold(Pos::zero(), |a, &b| a + b) * (1. / pts.len() as f64);
+            fills.push((center, *c));
+        }
     }
+
+    // Without any tile there is nothing to build a palette from
+    if (args.quantize.is_some() || !args.palette.is_empty()) && !fills.is_empty() {
+        if verbose.prog {
+            println!("Restricting colors to palette");
+        }
+        let colors = fills.iter().map(|f| f.1).collect::<Vec<_>>();
+        let palette = match &args.palette[..] {
+            "" => Palette::median_cut(&colors, args.quantize.unwrap()),
+            "grayscale" => Palette::grayscale(args.quantize.unwrap_or(16)),
+            "eink7" => Palette::eink7(),
+            file => Palette::from_gpl(file).unwrap_or_else(|e| {
+                println!("Could not read palette '{}': {}", file, e);
+                exit(1);
+            }),
+        };
+        let palette = match args.quantize {
+            Some(n) => palette.restrict(&colors, n),
+            None => palette,
+        };
+        if verbose.details {
+            println!("Palette: {:?}", palette);
+        }
+        let colors = if args.dither {
+            palette.dither(&fills)
+        } else {
+            colors.into_iter().map(|c| palette.nearest(c)).collect()
+        };
+        for (f, c) in fills.iter_mut().zip(colors) {
+            f.1 = c;
+        }
+        stroke = palette.nearest(stroke);
+    }
+
     let mut document = Document::new(cfg.frame);
-    for (pos, elem) in cfg.make_tiling(&mut rng) {
-        let fill = scene.color(pos, &mut rng);
+    let mut fills = fills.into_iter().map(|f| f.1);
+    for (_, elem, _, pieces) in tiles {
+        let fill = fills.next().unwrap();
+        let outline = (!stroke_like_fill && !pieces.is_empty()).then(|| elem.clone());
         document.add(
             elem.with_fill_color(fill)
                 .with_stroke_color(if stroke_like_fill { fill } else { stroke })
                 .with_stroke_width(stroke_width.max(0.1)),
         );
+        // Pieces have no outline of their own, only enough to hide the seams between them
+        for (piece, _) in pieces {
+            let fill = fills.next().unwrap();
+            document.add(
+                piece
+                    .with_fill_color(fill)
+                    .with_stroke_color(fill)
+                    .with_stroke_width(0.5),
+            );
+        }
+        // Pieces cover the outline of the tile and of its neighbors, draw it again
+        if let Some(outline) = outline {
+            document.add(
+                outline
+                    .without_fill()
+                    .with_stroke_color(stroke)
+                    .with_stroke_width(stroke_width),
+            );
+        }
     }
 
-    if dest == "" {
+    if dest.is_empty() {
         if verbose.prog {
             println!("No destination specified");
         }
@@ -226,6 +353,12 @@ struct Args {
     init: String,
     width: Option<usize>,
     height: Option<usize>,
+    quantize: Option<usize>,
+    palette: String,
+    dither: bool,
+    filter: Filter,
+    seed: Option<u64>,
+    threads: Option<usize>,
 }
 
 fn read_command_line_arguments() -> Args {
@@ -234,8 +367,13 @@ fn read_command_line_arguments() -> Args {
     let mut it = args_split.iter().skip(1).flat_map(|s| s.split('='));
 
     loop {
-        match it.next().as_deref() {
-            None => return args,
+        match it.next() {
+            None => {
+                if args.dither && args.quantize.is_none() && args.palette.is_empty() {
+                    panic!("Option --dither needs --quantize or --palette")
+                }
+                return args;
+            }
             Some("--help") => args.help = true,
             Some("--log") => {
                 args.log = it
@@ -248,17 +386,21 @@ fn read_command_line_arguments() -> Args {
             Some("--load") => {
                 args.load = it
                     .next()
-                    .unwrap_or_else(|| {
-                        panic!("Option --load should be followed by a source file")
-                    })
+                    .unwrap_or_else(|| panic!("Option --load should be followed by a source file"))
                     .to_string()
             }
-            Some("--verbose") => args.verbose = Verbosity::from(&it.next().unwrap_or_else(|| panic!("Option --verbose should be followed by a verbosity descriptor: '^[PDIWA]*$',
+            Some("--verbose") => {
+                args.verbose = Verbosity::from(it.next().unwrap_or_else(|| {
+                    panic!(
+                    "Option --verbose should be followed by a verbosity descriptor: '^[PDIWA]*$',
 P: Progress
 D: Details
 I: Info
 W: Warnings
-A: All"))[..]),
+A: All"
+                )
+                }))
+            }
             Some("--init") => {
                 args.init = it
                     .next()
@@ -313,11 +455,88 @@ A: All"))[..]),
                         .unwrap_or_else(|e| panic!("Failed to parse width: {}", e)),
                 )
             }
+            Some("--quantize") => {
+                let n = it
+                    .next()
+                    .unwrap_or_else(|| {
+                        panic!("Option --quantize should be followed by a positive integer")
+                    })
+                    .parse()
+                    .unwrap_or_else(|e| panic!("Failed to parse number of colors: {}", e));
+                if n == 0 {
+                    panic!("Option --quantize should be followed by a positive integer")
+                }
+                args.quantize = Some(n)
+            }
+            Some("--palette") => {
+                args.palette = it
+                    .next()
+                    .unwrap_or_else(|| {
+                        panic!("Option --palette should be followed by 'grayscale', 'eink7' or a .gpl file")
+                    })
+                    .to_string()
+            }
+            Some("--dither") => args.dither = true,
+            Some("--grayscale") => args.filter.grayscale = Some(true),
+            Some("--sepia") => args.filter.sepia = Some(true),
+            Some("--duotone") => {
+                let colors = it
+                    .next()
+                    .unwrap_or_else(|| {
+                        panic!("Option --duotone should be followed by two colors: '#RRGGBB,#RRGGBB'")
+                    })
+                    .split(',')
+                    .map(|c| {
+                        Color::from_hex(c).unwrap_or_else(|| panic!("Failed to parse color: {}", c))
+                    })
+                    .collect::<Vec<_>>();
+                match colors[..] {
+                    [dark, light] => args.filter.duotone = Some((dark, light)),
+                    _ => panic!("Option --duotone expects exactly two colors"),
+                }
+            }
+            Some("--hue") => args.filter.hue = Some(parse_number(it.next(), "--hue")),
+            Some("--saturation") => {
+                args.filter.saturation = Some(parse_number(it.next(), "--saturation"))
+            }
+            Some("--brightness") => {
+                args.filter.brightness = Some(parse_number(it.next(), "--brightness"))
+            }
+            Some("--contrast") => args.filter.contrast = Some(parse_number(it.next(), "--contrast")),
+            Some("--temperature") => {
+                args.filter.temperature = Some(parse_number(it.next(), "--temperature"))
+            }
+            Some("--seed") => {
+                args.seed = Some(
+                    it.next()
+                        .unwrap_or_else(|| {
+                            panic!("Option --seed should be followed by a positive integer")
+                        })
+                        .parse()
+                        .unwrap_or_else(|e| panic!("Failed to parse seed: {}", e)),
+                )
+            }
+            Some("--threads") => {
+                args.threads = Some(
+                    it.next()
+                        .unwrap_or_else(|| {
+                            panic!("Option --threads should be followed by a positive integer")
+                        })
+                        .parse()
+                        .unwrap_or_else(|e| panic!("Failed to parse number of threads: {}", e)),
+                )
+            }
             Some(o) => panic!("Unknown option {}", o),
         }
     }
 }
 
+fn parse_number(s: Option<&str>, option: &str) -> f64 {
+    s.unwrap_or_else(|| panic!("Option {} should be followed by a number", option))
+        .parse()
+        .unwrap_or_else(|e| panic!("Failed to parse {}: {}", option, e))
+}
+
 fn print_help() {
     print!(include_str!("../assets/man"));
 }
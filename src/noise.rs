@@ -0,0 +1,146 @@
+use crate::prelude::*;
+use rand::{rngs::StdRng, Rng};
+use serde_derive::{Deserialize, Serialize};
+
+/// Available flavors of coherent noise
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum NoiseKind {
+    Value,
+    Perlin,
+    Simplex,
+}
+
+impl NoiseKind {
+    pub fn from(s: &str) -> Option<Self> {
+        match s {
+            "value" => Some(NoiseKind::Value),
+            "perlin" => Some(NoiseKind::Perlin),
+            "simplex" => Some(NoiseKind::Simplex),
+            _ => None,
+        }
+    }
+}
+
+/// Fractal noise field, fully determined by its seed and parameters.
+/// No permutation table is stored: lattice values are obtained by hashing,
+/// which keeps the field `Copy` and trivial to log.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Noise {
+    pub kind: NoiseKind,
+    pub seed: u64,
+    pub scale: f64,
+    pub octaves: usize,
+}
+
+impl Noise {
+    /// Same parameters, new random seed
+    pub fn reseed(mut self, rng: &mut StdRng) -> Self {
+        self.seed = rng.gen();
+        self
+    }
+
+    /// Independent field with the same parameters (e.g. one per color channel)
+    pub fn channel(mut self, idx: u64) -> Self {
+        self.seed = mix(self.seed.wrapping_add(idx));
+        self
+    }
+
+    /// Sum of octaves, roughly within [-1; 1]
+    pub fn sample(&self, p: Pos) -> f64 {
+        let (mut x, mut y) = (p.0 / self.scale, p.1 / self.scale);
+        let mut amplitude = 1.;
+        let mut total = 0.;
+        let mut norm = 0.;
+        for oct in 0..self.octaves.max(1) {
+            let seed = mix(self.seed ^ oct as u64);
+            total += amplitude
+                * match self.kind {
+                    NoiseKind::Value => value(seed, x, y),
+                    NoiseKind::Perlin => perlin(seed, x, y),
+                    NoiseKind::Simplex => simplex(seed, x, y),
+                };
+            norm += amplitude * amplitude;
+            amplitude *= 0.5;
+            x *= 2.;
+            y *= 2.;
+        }
+        (total / norm.sqrt()).clamp(-1., 1.)
+    }
+}
+
+/// Bit mixer (from SplitMix64)
+fn mix(mut h: u64) -> u64 {
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^ (h >> 31)
+}
+
+/// Pseudo-random number in [0; 1) attached to a lattice point
+fn hash(seed: u64, i: i64, j: i64) -> f64 {
+    let h = mix(seed ^ mix((i as u64) ^ mix(j as u64).rotate_left(32)));
+    (h >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+fn gradient(seed: u64, i: i64, j: i64) -> (f64, f64) {
+    let theta = hash(seed, i, j) * std::f64::consts::PI * 2.;
+    (theta.cos(), theta.sin())
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+fn value(seed: u64, x: f64, y: f64) -> f64 {
+    let (i, j) = (x.floor() as i64, y.floor() as i64);
+    let (u, v) = (fade(x - x.floor()), fade(y - y.floor()));
+    let corner = |di, dj| hash(seed, i + di, j + dj) * 2. - 1.;
+    lerp(
+        lerp(corner(0, 0), corner(1, 0), u),
+        lerp(corner(0, 1), corner(1, 1), u),
+        v,
+    )
+}
+
+fn perlin(seed: u64, x: f64, y: f64) -> f64 {
+    let (i, j) = (x.floor() as i64, y.floor() as i64);
+    let (fx, fy) = (x - x.floor(), y - y.floor());
+    let corner = |di: i64, dj: i64| {
+        let (gx, gy) = gradient(seed, i + di, j + dj);
+        gx * (fx - di as f64) + gy * (fy - dj as f64)
+    };
+    let (u, v) = (fade(fx), fade(fy));
+    // Unit gradients give values within [-sqrt(0.5); sqrt(0.5)]
+    lerp(
+        lerp(corner(0, 0), corner(1, 0), u),
+        lerp(corner(0, 1), corner(1, 1), u),
+        v,
+    ) * 2_f64.sqrt()
+}
+
+fn simplex(seed: u64, x: f64, y: f64) -> f64 {
+    let f2 = 0.5 * (3_f64.sqrt() - 1.);
+    let g2 = (3. - 3_f64.sqrt()) / 6.;
+    let s = (x + y) * f2;
+    let (i, j) = ((x + s).floor() as i64, (y + s).floor() as i64);
+    let t = (i + j) as f64 * g2;
+    let (x0, y0) = (x - (i as f64 - t), y - (j as f64 - t));
+    let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+    let corners = [
+        (0, 0, x0, y0),
+        (i1, j1, x0 - i1 as f64 + g2, y0 - j1 as f64 + g2),
+        (1, 1, x0 - 1. + 2. * g2, y0 - 1. + 2. * g2),
+    ];
+    let mut total = 0.;
+    for &(di, dj, dx, dy) in corners.iter() {
+        let falloff = 0.5 - dx * dx - dy * dy;
+        if falloff > 0. {
+            let (gx, gy) = gradient(seed, i + di, j + dj);
+            total += falloff.powi(4) * (gx * dx + gy * dy);
+        }
+    }
+    total * 70.
+}
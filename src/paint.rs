@@ -1,17 +1,20 @@
 use crate::cfg::SceneCfg;
+use crate::noise::{Noise, NoiseKind};
 use crate::prelude::*;
 use crate::scene::*;
-use rand::{rngs::ThreadRng, Rng};
+use crate::svg::Path;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
+use std::collections::{HashMap, HashSet};
 
-pub fn create_free_circles(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Disc> {
+pub fn create_free_circles(rng: &mut StdRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Disc> {
     let mut items = Vec::new();
-    for i in 1..=cfg.nb_pattern {
+    for i in 1..=cfg.params.nb_pattern {
         let c = cfg.choose_color(rng);
         items.push(Disc::random(
             rng,
             &cfg.frame,
             c,
-            i as f64 / cfg.nb_pattern as f64 * 0.5,
+            i as f64 / cfg.params.nb_pattern as f64 * 0.5,
         ));
     }
     items.sort_by(|a, b| a.radius.partial_cmp(&b.radius).unwrap());
@@ -22,18 +25,18 @@ pub fn create_free_circles(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosi
 }
 
 pub fn create_free_triangles(
-    rng: &mut ThreadRng,
+    rng: &mut StdRng,
     cfg: &SceneCfg,
     verbose: Verbosity,
 ) -> Vec<Triangle> {
     let mut items = Vec::new();
-    for i in 1..=cfg.nb_pattern {
+    for i in 1..=cfg.params.nb_pattern {
         let c = cfg.choose_color(rng);
         items.push(Disc::random(
             rng,
             &cfg.frame,
             c,
-            i as f64 / cfg.nb_pattern as f64 * 0.7,
+            i as f64 / cfg.params.nb_pattern as f64 * 0.7,
         ));
     }
     items.sort_by(|a, b| a.radius.partial_cmp(&b.radius).unwrap());
@@ -47,11 +50,168 @@ pub fn create_free_triangles(
     items
 }
 
-pub fn create_free_stripes(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Stripe> {
+/// How the sizes of free polygons are spread between the smallest and the largest
+#[derive(Debug, Clone, Copy)]
+pub enum SizeDistribution {
+    /// Evenly spaced sizes
+    Linear,
+    /// Independent random sizes
+    Uniform,
+    /// Mostly small items and a few large ones
+    Power,
+}
+
+impl SizeDistribution {
+    pub fn from(s: &str) -> Option<Self> {
+        match s {
+            "linear" => Some(SizeDistribution::Linear),
+            "uniform" => Some(SizeDistribution::Uniform),
+            "power" => Some(SizeDistribution::Power),
+            _ => None,
+        }
+    }
+
+    /// Size of the i-th out of n items, as a fraction between 0 and 1
+    fn sample(self, rng: &mut StdRng, i: usize, n: usize) -> f64 {
+        match self {
+            SizeDistribution::Linear => i as f64 / n.max(1) as f64,
+            SizeDistribution::Uniform => rng.gen::<f64>(),
+            SizeDistribution::Power => rng.gen::<f64>().powi(3),
+        }
+    }
+}
+
+pub fn create_free_polygons(rng: &mut StdRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Polygon> {
+    let (min_sides, max_sides) = cfg.params.sides_polygons;
+    let (min_size, max_size) = cfg.params.size_polygons;
+    let mut items = Vec::new();
+    for i in 1..=cfg.params.nb_pattern {
+        let sides = rng.gen_range(min_sides, max_sides + 1);
+        // Steps that draw a single connected star
+        let steps = (2..=(sides - 1) / 2)
+            .filter(|&k| gcd(sides, k) == 1)
+            .collect::<Vec<_>>();
+        let step = if !steps.is_empty() && rng.gen::<f64>() < cfg.params.star_polygons {
+            steps[rng.gen_range(0, steps.len())]
+        } else {
+            1
+        };
+        let size = min_size
+            + (max_size - min_size)
+                * cfg
+                    .params
+                    .distribution_polygons
+                    .sample(rng, i, cfg.params.nb_pattern);
+        let rotation = -90 + rng.gen_range(0, cfg.params.rotation_polygons + 1);
+        items.push(Polygon::new(
+            Pos::random(&cfg.frame, rng),
+            size * cfg.frame.h.min(cfg.frame.w) as f64,
+            sides,
+            step,
+            rotation,
+            cfg.choose_color(rng),
+        ));
+    }
+    items.sort_by(|a, b| a.radius.partial_cmp(&b.radius).unwrap());
+    if verbose.details {
+        println!("{:#?}", items);
+    }
+    items
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Equations from which curve items are drawn
+#[derive(Debug, Clone, Copy)]
+pub enum CurveFamily {
+    Rose,
+    Lissajous,
+    Superformula,
+}
+
+impl CurveFamily {
+    pub fn from(s: &str) -> Option<Self> {
+        match s {
+            "rose" => Some(CurveFamily::Rose),
+            "lissajous" => Some(CurveFamily::Lissajous),
+            "superformula" => Some(CurveFamily::Superformula),
+            _ => None,
+        }
+    }
+
+    /// Random equation of the family with a number of petals, lobes or symmetries within the given range
+    fn choose(self, rng: &mut StdRng, (lo, hi): (usize, usize), width: f64) -> CurveKind {
+        let k = rng.gen_range(lo, hi + 1);
+        match self {
+            CurveFamily::Rose => CurveKind::Rose {
+                n: k,
+                d: rng.gen_range(1, 4),
+            },
+            CurveFamily::Lissajous => CurveKind::Lissajous {
+                a: k,
+                // Equal or non coprime frequencies only retrace a simpler curve
+                b: *(lo..=hi)
+                    .filter(|&b| b != k && gcd(k, b) == 1)
+                    .collect::<Vec<_>>()
+                    .choose(rng)
+                    .unwrap_or(&(k + 1)),
+                phase: rng.gen::<f64>() * std::f64::consts::PI,
+                ratio: rng.gen::<f64>() * 0.5 + 0.5,
+                width,
+            },
+            CurveFamily::Superformula => CurveKind::Superformula {
+                m: k as f64,
+                n1: rng.gen::<f64>() * 2.5 + 0.5,
+                n2: rng.gen::<f64>() * 2.5 + 0.5,
+                n3: rng.gen::<f64>() * 2.5 + 0.5,
+            },
+        }
+    }
+}
+
+pub fn create_curves(rng: &mut StdRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Curve> {
+    let (min_size, max_size) = cfg.params.size_curves;
+    let h = cfg.frame.h as f64;
+    let mut items = Vec::new();
+    for _ in 0..cfg.params.nb_pattern {
+        // An empty list of families means all of them
+        let family = match cfg.params.families_curves.choose(rng) {
+            Some(&family) => family,
+            None => *[
+                CurveFamily::Rose,
+                CurveFamily::Lissajous,
+                CurveFamily::Superformula,
+            ]
+            .choose(rng)
+            .unwrap(),
+        };
+        let kind = family.choose(rng, cfg.params.petals_curves, cfg.params.width_curves * h);
+        items.push(Curve::new(
+            Pos::random(&cfg.frame, rng),
+            (min_size + (max_size - min_size) * rng.gen::<f64>()) * h,
+            rng.gen_range(0, 360),
+            kind,
+            cfg.choose_color(rng),
+        ));
+    }
+    items.sort_by(|a, b| a.size.partial_cmp(&b.size).unwrap());
+    if verbose.details {
+        println!("{:#?}", items);
+    }
+    items
+}
+
+pub fn create_free_stripes(rng: &mut StdRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Stripe> {
     let mut items = Vec::new();
-    for _ in 0..cfg.nb_pattern {
+    for _ in 0..cfg.params.nb_pattern {
         let c = cfg.choose_color(rng);
-        let w = cfg.width_pattern * cfg.frame.h as f64 * (rng.gen::<f64>() + 0.5);
+        let w = cfg.params.width_pattern * cfg.frame.h as f64 * (rng.gen::<f64>() + 0.5);
         items.push(Stripe::random(rng, &cfg.frame, c, w));
     }
     if verbose.details {
@@ -60,12 +220,18 @@ pub fn create_free_stripes(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosi
     items
 }
 
-pub fn create_free_spirals(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Spiral> {
+pub fn create_free_spirals(rng: &mut StdRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Spiral> {
     let mut items = Vec::new();
-    for _ in 0..cfg.nb_pattern {
+    for _ in 0..cfg.params.nb_pattern {
         let c = cfg.choose_color(rng);
-        let w = cfg.width_pattern * cfg.frame.h as f64 * (rng.gen::<f64>() + 0.5);
-        items.push(Spiral::random(rng, &cfg.frame, c, w, cfg.tightness_spiral));
+        let w = cfg.params.width_pattern * cfg.frame.h as f64 * (rng.gen::<f64>() + 0.5);
+        items.push(Spiral::random(
+            rng,
+            &cfg.frame,
+            c,
+            w,
+            cfg.params.tightness_spiral,
+        ));
     }
     items.sort_by(|a, b| a.width.partial_cmp(&b.width).unwrap());
     if verbose.details {
@@ -75,7 +241,7 @@ pub fn create_free_spirals(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosi
 }
 
 pub fn create_concentric_circles(
-    rng: &mut ThreadRng,
+    rng: &mut StdRng,
     cfg: &SceneCfg,
     verbose: Verbosity,
 ) -> Vec<Disc> {
@@ -86,10 +252,10 @@ pub fn create_concentric_circles(
         .max(center.dist(Pos(0., cfg.frame.w as f64)))
         .max(center.dist(Pos(cfg.frame.h as f64, 0.)))
         .max(center.dist(Pos(cfg.frame.h as f64, cfg.frame.w as f64)));
-    for i in 1..=cfg.nb_pattern {
+    for i in 1..=cfg.params.nb_pattern {
         items.push(Disc {
             center,
-            radius: d * i as f64 / cfg.nb_pattern as f64,
+            radius: d * i as f64 / cfg.params.nb_pattern as f64,
             color: cfg.choose_color(rng),
         })
     }
@@ -100,8 +266,120 @@ pub fn create_concentric_circles(
     items
 }
 
+pub fn create_arcs(rng: &mut StdRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Annulus> {
+    let f = &cfg.frame;
+    let corners = [
+        Pos(f.x as f64, f.y as f64),
+        Pos((f.x + f.w) as f64, f.y as f64),
+        Pos(f.x as f64, (f.y + f.h) as f64),
+        Pos((f.x + f.w) as f64, (f.y + f.h) as f64),
+    ];
+    let rings = cfg.params.nb_pattern.max(1);
+    let mut items = Vec::new();
+    for _ in 0..cfg.params.centers_arcs.max(1) {
+        let center = Pos::random(f, rng);
+        let reach = corners.iter().map(|&c| center.dist(c)).fold(0., f64::max);
+        let spacing = reach / rings as f64;
+        for k in 0..rings {
+            let mid = spacing * (k as f64 + 0.5);
+            let thickness = spacing * cfg.params.width_arcs * (rng.gen::<f64>() * 0.7 + 0.3);
+            // Each ring is broken into a few segments separated by gaps
+            let segments = rng.gen_range(1, 4);
+            let part = std::f64::consts::PI * 2. / segments as f64;
+            let offset = rng.gen::<f64>() * part;
+            for i in 0..segments {
+                let width = part * (1. - cfg.params.gap_arcs * (rng.gen::<f64>() * 0.5 + 0.5));
+                items.push(Annulus {
+                    center,
+                    inner: mid - thickness / 2.,
+                    outer: mid + thickness / 2.,
+                    start: offset + part * i as f64,
+                    width,
+                    color: cfg.choose_color(rng),
+                });
+            }
+        }
+    }
+    if verbose.details {
+        println!("{:#?}", items);
+    }
+    items
+}
+
+/// Where the rays of a sunburst meet
+#[derive(Debug, Clone, Copy)]
+pub enum RayCenter {
+    Random,
+    /// Center of the frame
+    Frame,
+    /// One of the four intersections of the lines that cut the frame in thirds
+    Thirds,
+}
+
+impl RayCenter {
+    pub fn from(s: &str) -> Option<Self> {
+        match s {
+            "random" => Some(RayCenter::Random),
+            "center" => Some(RayCenter::Frame),
+            "thirds" => Some(RayCenter::Thirds),
+            _ => None,
+        }
+    }
+
+    fn choose(self, rng: &mut StdRng, f: &Frame) -> Pos {
+        match self {
+            RayCenter::Random => Pos::random(f, rng),
+            RayCenter::Frame => f.center(),
+            RayCenter::Thirds => Pos(
+                f.x as f64 + f.w as f64 * rng.gen_range(1, 3) as f64 / 3.,
+                f.y as f64 + f.h as f64 * rng.gen_range(1, 3) as f64 / 3.,
+            ),
+        }
+    }
+}
+
+pub fn create_radial_rays(rng: &mut StdRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Sector> {
+    let mut n = cfg.params.nb_pattern.max(2);
+    // Two colors only alternate properly around an even number of rays
+    if cfg.params.alternate_rays && n % 2 == 1 {
+        n += 1;
+    }
+    let center = cfg.params.center_rays.choose(rng, &cfg.frame);
+    let sector = std::f64::consts::PI * 2. / n as f64;
+    let offset = rng.gen::<f64>() * sector;
+    // Jitter less than half a sector on each limit keeps them in order
+    let limits = (0..n)
+        .map(|i| offset + sector * (i as f64 + (rng.gen::<f64>() - 0.5) * cfg.params.jitter_rays))
+        .collect::<Vec<_>>();
+    let twist = radians(cfg.params.twist_rays) / cfg.frame.h as f64;
+    let alternate = [cfg.choose_color(rng), cfg.choose_color(rng)];
+    let mut items = Vec::new();
+    for i in 0..n {
+        let end = if i + 1 < n {
+            limits[i + 1]
+        } else {
+            limits[0] + std::f64::consts::PI * 2.
+        };
+        items.push(Sector {
+            center,
+            start: limits[i],
+            width: end - limits[i],
+            twist,
+            color: if cfg.params.alternate_rays {
+                alternate[i % 2].clone()
+            } else {
+                cfg.choose_color(rng)
+            },
+        });
+    }
+    if verbose.details {
+        println!("{:#?}", items);
+    }
+    items
+}
+
 pub fn create_parallel_stripes(
-    rng: &mut ThreadRng,
+    rng: &mut StdRng,
     cfg: &SceneCfg,
     verbose: Verbosity,
 ) -> Vec<HalfPlane> {
@@ -113,14 +391,14 @@ pub fn create_parallel_stripes(
         let d = Pos::polar(dir, w as f64 / 2.);
         (c + d, c - d, dir)
     };
-    for i in 0..=cfg.nb_pattern {
+    for i in 0..=cfg.params.nb_pattern {
         let c = cfg.choose_color(rng);
-        let p = i as f64 / cfg.nb_pattern as f64;
+        let p = i as f64 / cfg.params.nb_pattern as f64;
         items.push(HalfPlane::random(
             rng,
             a * (1. - p) + b * p,
             180 + dir,
-            cfg.var_stripes,
+            cfg.params.var_stripes,
             c,
         ));
     }
@@ -131,7 +409,7 @@ pub fn create_parallel_stripes(
 }
 
 pub fn create_crossed_stripes(
-    rng: &mut ThreadRng,
+    rng: &mut StdRng,
     cfg: &SceneCfg,
     verbose: Verbosity,
 ) -> Vec<HalfPlane> {
@@ -144,14 +422,14 @@ pub fn create_crossed_stripes(
         let d_orth = Pos::polar(dir + 90, w as f64 / 2.);
         (c + d, c - d, c - d_orth, c + d_orth, dir)
     };
-    for i in 0..=cfg.nb_pattern {
-        let p = i as f64 / cfg.nb_pattern as f64;
+    for i in 0..=cfg.params.nb_pattern {
+        let p = i as f64 / cfg.params.nb_pattern as f64;
         let c = cfg.choose_color(rng);
         items.push(HalfPlane::random(
             rng,
             a * (1. - p) + b * p,
             180 + dir,
-            cfg.var_stripes,
+            cfg.params.var_stripes,
             c,
         ));
         let c = cfg.choose_color(rng);
@@ -159,7 +437,7 @@ pub fn create_crossed_stripes(
             rng,
             a_orth * (1. - p) + b_orth * p,
             90 + dir,
-            cfg.var_stripes,
+            cfg.params.var_stripes,
             c,
         ));
     }
@@ -169,7 +447,7 @@ pub fn create_crossed_stripes(
     items
 }
 
-pub fn create_waves(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Wave> {
+pub fn create_waves(rng: &mut StdRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Wave> {
     let mut items = Vec::new();
     let (a, b, dir) = {
         let c = cfg.frame.center();
@@ -178,15 +456,15 @@ pub fn create_waves(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosity) ->
         let d = Pos::polar(dir, w as f64 / 2.);
         (c + d, c - d, dir)
     };
-    let amplitude = (b - a).norm() / cfg.nb_pattern as f64 / 2.;
-    for i in 0..=cfg.nb_pattern {
+    let amplitude = (b - a).norm() / cfg.params.nb_pattern as f64 / 2.;
+    for i in 0..=cfg.params.nb_pattern {
         let c = cfg.choose_color(rng);
-        let p = i as f64 / cfg.nb_pattern as f64;
+        let p = i as f64 / cfg.params.nb_pattern as f64;
         items.push(Wave::random(
             rng,
             a * (1. - p) + b * p,
             180 + dir,
-            cfg.width_pattern / 5.,
+            cfg.params.width_pattern / 5.,
             amplitude,
             c,
         ));
@@ -197,7 +475,7 @@ pub fn create_waves(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosity) ->
     items
 }
 
-pub fn create_sawteeth(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Sawtooth> {
+pub fn create_sawteeth(rng: &mut StdRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Sawtooth> {
     let mut items = Vec::new();
     let (a, b, dir) = {
         let c = cfg.frame.center();
@@ -206,15 +484,15 @@ pub fn create_sawteeth(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosity)
         let d = Pos::polar(dir, w as f64 / 2.);
         (c + d, c - d, dir)
     };
-    let amplitude = (b - a).norm() / cfg.nb_pattern as f64 / 2.;
-    for i in 0..=cfg.nb_pattern {
+    let amplitude = (b - a).norm() / cfg.params.nb_pattern as f64 / 2.;
+    for i in 0..=cfg.params.nb_pattern {
         let c = cfg.choose_color(rng);
-        let p = i as f64 / cfg.nb_pattern as f64;
+        let p = i as f64 / cfg.params.nb_pattern as f64;
         items.push(Sawtooth::random(
             rng,
             a * (1. - p) + b * p,
             180 + dir,
-            cfg.width_pattern / 5.,
+            cfg.params.width_pattern / 5.,
             amplitude,
             c,
         ));
@@ -224,3 +502,459 @@ pub fn create_sawteeth(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosity)
     }
     items
 }
+
+pub fn create_voronoi(rng: &mut StdRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Voronoi> {
+    let n = cfg.params.nb_pattern.max(1);
+    let spacing = ((cfg.frame.w * cfg.frame.h) as f64 / n as f64).sqrt();
+    let centers = (0..n)
+        .map(|_| Pos::random(&cfg.frame, rng))
+        .collect::<Vec<_>>();
+    let weights = (0..n)
+        .map(|_| rng.gen::<f64>() * cfg.params.weight_voronoi * spacing)
+        .collect::<Vec<_>>();
+    let sites = Shared::new(Sites {
+        metric: cfg.params.metric_voronoi,
+        centers,
+        weights,
+    });
+    let items = (0..n)
+        .map(|index| Voronoi {
+            sites: sites.clone(),
+            index,
+            color: cfg.choose_color(rng),
+        })
+        .collect::<Vec<_>>();
+    if verbose.details {
+        println!("{:#?}", sites.inner);
+    }
+    items
+}
+
+pub fn create_contours(rng: &mut StdRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Contour> {
+    let noise = Noise {
+        kind: NoiseKind::Perlin,
+        seed: rng.gen(),
+        scale: cfg.params.scale_contours * cfg.frame.h as f64,
+        octaves: cfg.params.octaves_contours,
+    };
+    // Spread the levels over the elevations that actually appear in the frame
+    let (mut lo, mut hi) = (f64::INFINITY, f64::NEG_INFINITY);
+    for i in 0..=CONTOUR_SAMPLES {
+        for j in 0..=CONTOUR_SAMPLES {
+            let p = Pos(
+                cfg.frame.x as f64 + cfg.frame.w as f64 * i as f64 / CONTOUR_SAMPLES as f64,
+                cfg.frame.y as f64 + cfg.frame.h as f64 * j as f64 / CONTOUR_SAMPLES as f64,
+            );
+            let z = noise.sample(p);
+            lo = lo.min(z);
+            hi = hi.max(z);
+        }
+    }
+    let n = cfg.params.nb_pattern.max(1);
+    let mut items = (0..n)
+        .map(|i| Contour {
+            noise,
+            // The lowest band covers everything below the others
+            level: if i == 0 {
+                -1.
+            } else {
+                lo + (hi - lo) * i as f64 / n as f64
+            },
+            terrace: cfg.params.terrace_contours,
+            color: cfg.choose_color(rng),
+        })
+        .collect::<Vec<_>>();
+    items.reverse();
+    if verbose.details {
+        println!("{:#?}", items);
+    }
+    items
+}
+
+pub fn create_flow_field(rng: &mut StdRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Stream> {
+    let scale = cfg.params.scale_flow * cfg.frame.h as f64;
+    let flow = if cfg.params.vortices_flow == 0 {
+        Flow::Noise(Noise {
+            kind: NoiseKind::Simplex,
+            seed: rng.gen(),
+            scale,
+            octaves: 2,
+        })
+    } else {
+        // Vortices may lie a bit outside of the frame
+        let f = &cfg.frame;
+        let margin = Frame {
+            x: f.x.saturating_sub(f.w / 4),
+            y: f.y.saturating_sub(f.h / 4),
+            w: f.w * 3 / 2,
+            h: f.h * 3 / 2,
+        };
+        Flow::Vortices(
+            (0..cfg.params.vortices_flow)
+                .map(|_| {
+                    let strength = (rng.gen::<f64>() + 0.5) * if rng.gen() { 1. } else { -1. };
+                    (Pos::random(&margin, rng), strength)
+                })
+                .collect(),
+        )
+    };
+    let bands = cfg.params.nb_pattern.max(1);
+    let field = Shared::new(Streamlines {
+        flow,
+        bands,
+        width: cfg.params.width_flow,
+    });
+    let items = (0..bands)
+        .map(|band| Stream {
+            field: field.clone(),
+            band,
+            color: cfg.choose_color(rng),
+        })
+        .collect::<Vec<_>>();
+    if verbose.details {
+        println!("{:#?}", field.inner);
+    }
+    items
+}
+
+/// Kind of fractal set drawn by escape time
+#[derive(Debug, Clone, Copy)]
+pub enum FractalKind {
+    Julia,
+    Mandelbrot,
+}
+
+impl FractalKind {
+    pub fn from(s: &str) -> Option<Self> {
+        match s {
+            "julia" => Some(FractalKind::Julia),
+            "mandelbrot" => Some(FractalKind::Mandelbrot),
+            _ => None,
+        }
+    }
+}
+
+/// Centers and widths of windows of the Mandelbrot set that show a lot of detail
+const MANDELBROT_WINDOWS: [(f64, f64, f64); 5] = [
+    (-0.5, 0., 3.),
+    (-0.745, 0.11, 0.05),
+    (0.275, 0.007, 0.03),
+    (-1.77, 0., 0.1),
+    (-0.1011, 0.9563, 0.02),
+];
+
+/// Constants that give connected and intricate Julia sets
+const JULIA_CONSTANTS: [(f64, f64); 7] = [
+    (-0.8, 0.156),
+    (-0.4, 0.6),
+    (0.285, 0.01),
+    (-0.70176, -0.3842),
+    (-0.835, -0.2321),
+    (0.355, 0.355),
+    (-0.7269, 0.1889),
+];
+
+pub fn create_fractal(rng: &mut StdRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Escape> {
+    let kind = cfg.params.kind_fractal.unwrap_or_else(|| {
+        if rng.gen() {
+            FractalKind::Julia
+        } else {
+            FractalKind::Mandelbrot
+        }
+    });
+    let (julia, center, width) = match kind {
+        FractalKind::Mandelbrot => {
+            let (x, y, w) = *MANDELBROT_WINDOWS.choose(rng).unwrap();
+            (None, Pos(x, y), w)
+        }
+        FractalKind::Julia => {
+            let (x, y) = *JULIA_CONSTANTS.choose(rng).unwrap();
+            let center = Pos(rng.gen::<f64>() - 0.5, rng.gen::<f64>() - 0.5) * 0.5;
+            (Some(Pos(x, y)), center, 3.2)
+        }
+    };
+    let width = width * (rng.gen::<f64>() * 0.6 + 0.7);
+    let bands = cfg.params.nb_pattern.max(1);
+    let fractal = Shared::new(EscapeTime {
+        julia,
+        origin: cfg.frame.center(),
+        center,
+        unit: width / cfg.frame.w.max(1) as f64,
+        iterations: cfg.params.iterations_fractal,
+        bands,
+    });
+    let items = (0..bands)
+        .map(|band| Escape {
+            fractal: fractal.clone(),
+            band,
+            color: cfg.choose_color(rng),
+        })
+        .collect::<Vec<_>>();
+    if verbose.details {
+        println!("{:#?}", fractal.inner);
+    }
+    items
+}
+
+/// Gradient across the whole frame, in a random direction or around a random center
+fn random_gradient(
+    rng: &mut StdRng,
+    cfg: &SceneCfg,
+    shape: GradientShape,
+    nb_colors: usize,
+    banded: bool,
+    jitter: f64,
+) -> Gradient {
+    let f = &cfg.frame;
+    let (start, end) = match shape {
+        GradientShape::Linear => {
+            let dir = Pos::polar(rng.gen_range(0, 360), 1.);
+            // Half the length of the projection of the frame on the axis
+            let reach = (f.w as f64 * dir.0.abs() + f.h as f64 * dir.1.abs()) / 2.;
+            (f.center() - dir * reach, f.center() + dir * reach)
+        }
+        GradientShape::Radial => {
+            let center = Pos::random(f, rng);
+            let corners = [
+                Pos(f.x as f64, f.y as f64),
+                Pos((f.x + f.w) as f64, f.y as f64),
+                Pos(f.x as f64, (f.y + f.h) as f64),
+                Pos((f.x + f.w) as f64, (f.y + f.h) as f64),
+            ];
+            let far = corners
+                .iter()
+                .copied()
+                .max_by(|a, b| center.dist(*a).partial_cmp(&center.dist(*b)).unwrap())
+                .unwrap();
+            (center, far)
+        }
+    };
+    Gradient {
+        shape,
+        start,
+        end,
+        banded,
+        jitter,
+        colors: (0..nb_colors.max(1))
+            .map(|_| cfg.choose_color(rng))
+            .collect(),
+    }
+}
+
+pub fn create_gradient(
+    rng: &mut StdRng,
+    cfg: &SceneCfg,
+    shape: GradientShape,
+    verbose: Verbosity,
+) -> Vec<Gradient> {
+    let gradient = random_gradient(
+        rng,
+        cfg,
+        shape,
+        cfg.params.nb_pattern,
+        cfg.params.bands_gradient,
+        cfg.params.jitter_gradient,
+    );
+    if verbose.details {
+        println!("{:#?}", gradient);
+    }
+    vec![gradient]
+}
+
+/// Gradient painted behind all items instead of a single color
+#[derive(Debug, Clone, Copy)]
+pub struct Background {
+    pub shape: GradientShape,
+    pub colors: usize,
+    pub banded: bool,
+    pub jitter: f64,
+}
+
+impl Background {
+    pub fn create(&self, rng: &mut StdRng, cfg: &SceneCfg) -> Gradient {
+        random_gradient(rng, cfg, self.shape, self.colors, self.banded, self.jitter)
+    }
+}
+
+/// How each cell of an automaton changes according to its neighbors
+#[derive(Debug, Clone)]
+pub enum Rule {
+    /// Two states; a dead cell comes alive with a number of living neighbors
+    /// in `birth`, a living cell stays alive with a number in `survive`
+    Life {
+        birth: Vec<usize>,
+        survive: Vec<usize>,
+    },
+    /// Each cell takes the most common state around it
+    Majority,
+    /// Each cell moves on to the next state when at least
+    /// `threshold` neighbors are already in it
+    Cyclic { threshold: usize },
+}
+
+impl Rule {
+    /// Number of states the cells can be in
+    fn states(&self, nb_pattern: usize) -> usize {
+        match self {
+            Rule::Life { .. } => 2,
+            Rule::Majority | Rule::Cyclic { .. } => nb_pattern.max(2),
+        }
+    }
+
+    fn next(&self, state: usize, around: &[usize], states: usize) -> usize {
+        match self {
+            Rule::Life { birth, survive } => {
+                let alive = around.iter().filter(|&&s| s == 1).count();
+                let rule = if state == 1 { survive } else { birth };
+                rule.contains(&alive) as usize
+            }
+            Rule::Majority => {
+                let mut count = vec![0; states];
+                count[state] += 1;
+                for &s in around {
+                    count[s] += 1;
+                }
+                // Ties keep the current state
+                (0..states)
+                    .filter(|&s| count[s] > count[state])
+                    .max_by_key(|&s| count[s])
+                    .unwrap_or(state)
+            }
+            Rule::Cyclic { threshold } => {
+                let succ = (state + 1) % states;
+                if around.iter().filter(|&&s| s == succ).count() >= (*threshold).max(1) {
+                    succ
+                } else {
+                    state
+                }
+            }
+        }
+    }
+}
+
+/// Indices of the tiles that share part of an edge with each tile.
+/// Edges only need to overlap, so that tilings whose vertices do not match
+/// exactly or fall in the middle of the edges of other tiles are handled.
+fn adjacency(tiles: &[(Pos, Path)]) -> Vec<Vec<usize>> {
+    let mut edges = Vec::new();
+    for (i, (_, path)) in tiles.iter().enumerate() {
+        let pts = &path.data.0;
+        for k in 0..pts.len() {
+            let (a, b) = (pts[k], pts[(k + 1) % pts.len()]);
+            if a.dist(b) > 0. {
+                edges.push((i, a, b));
+            }
+        }
+    }
+    let mean = edges.iter().map(|(_, a, b)| a.dist(*b)).sum::<f64>() / edges.len().max(1) as f64;
+    // Tolerance on the alignment of edges and on the length of their common part
+    let tol = mean * 0.05;
+    // A few very long edges (e.g. on the hull of a Delaunay triangulation)
+    // must not make the buckets huge, so they are sized after the typical edge
+    // and each edge is indexed in all the buckets that its segment goes through.
+    let side = mean.max(1.);
+    let cells = |a: Pos, b: Pos| {
+        let (lo, hi) = (
+            Pos(a.0.min(b.0) - tol, a.1.min(b.1) - tol),
+            Pos(a.0.max(b.0) + tol, a.1.max(b.1) + tol),
+        );
+        let reach = side * 0.5 * 2_f64.sqrt() + tol;
+        let mut found = Vec::new();
+        for x in (lo.0 / side).floor() as i64..=(hi.0 / side).floor() as i64 {
+            for y in (lo.1 / side).floor() as i64..=(hi.1 / side).floor() as i64 {
+                let center = Pos((x as f64 + 0.5) * side, (y as f64 + 0.5) * side);
+                if segment_distance(center, a, b) <= reach {
+                    found.push((x, y));
+                }
+            }
+        }
+        found
+    };
+    let edge_cells = edges
+        .iter()
+        .map(|&(_, a, b)| cells(a, b))
+        .collect::<Vec<_>>();
+    let mut buckets: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+    for (k, cs) in edge_cells.iter().enumerate() {
+        for &c in cs {
+            buckets.entry(c).or_default().push(k);
+        }
+    }
+    let mut neighbors = vec![HashSet::new(); tiles.len()];
+    for (&(i, a, b), cs) in edges.iter().zip(edge_cells.iter()) {
+        let len = a.dist(b);
+        let dir = (b - a) * (1. / len);
+        for &k in cs.iter().flat_map(|c| buckets.get(c).into_iter().flatten()) {
+            let (j, c, d) = edges[k];
+            if j == i || neighbors[i].contains(&j) {
+                continue;
+            }
+            let (tc, td) = ((c - a).dot(dir), (d - a).dot(dir));
+            let off = |p: Pos, t: f64| (p - a - dir * t).norm();
+            let aligned = off(c, tc) < tol && off(d, td) < tol;
+            let common = len.min(tc.max(td)) - tc.min(td).max(0.);
+            if aligned && common > tol {
+                neighbors[i].insert(j);
+            }
+        }
+    }
+    neighbors
+        .into_iter()
+        .map(|n| {
+            let mut n = n.into_iter().collect::<Vec<_>>();
+            n.sort_unstable();
+            n
+        })
+        .collect()
+}
+
+/// Distance from p to the segment [a, b]
+fn segment_distance(p: Pos, a: Pos, b: Pos) -> f64 {
+    let ab = b - a;
+    let t = ((p - a).dot(ab) / ab.dot_self().max(f64::EPSILON)).clamp(0., 1.);
+    p.dist(a + ab * t)
+}
+
+pub fn create_automaton(
+    rng: &mut StdRng,
+    cfg: &SceneCfg,
+    tiles: &[(Pos, Path)],
+    verbose: Verbosity,
+) -> Vec<Cell> {
+    let rule = &cfg.params.rule_automaton;
+    let states = rule.states(cfg.params.nb_pattern);
+    let neighbors = adjacency(tiles);
+    let mut cells = (0..tiles.len())
+        .map(|_| rng.gen_range(0, states))
+        .collect::<Vec<_>>();
+    let mut around = Vec::new();
+    for _ in 0..cfg.params.steps_automaton {
+        cells = (0..cells.len())
+            .map(|i| {
+                around.clear();
+                around.extend(neighbors[i].iter().map(|&j| cells[j]));
+                rule.next(cells[i], &around, states)
+            })
+            .collect();
+    }
+    if verbose.details {
+        let mut count = vec![0; states];
+        for &s in &cells {
+            count[s] += 1;
+        }
+        println!("{:?}: {:?} cells in each state", rule, count);
+    }
+    let automaton = Shared::new(Automaton::new(
+        tiles.iter().map(|(p, _)| *p).zip(cells).collect(),
+    ));
+    (0..states)
+        .map(|state| Cell {
+            automaton: automaton.clone(),
+            state,
+            color: cfg.choose_color(rng),
+        })
+        .collect()
+}
+
+/// Number of samples along each side of the frame used to find the range of elevations
+const CONTOUR_SAMPLES: usize = 50;
@@ -0,0 +1,247 @@
+use crate::prelude::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+
+/// A limited set of colors that the image is restricted to
+#[derive(Debug, Clone)]
+pub struct Palette(pub Vec<Color>);
+
+/// Color with floating point channels, used to accumulate errors
+type Rgb = (f64, f64, f64);
+
+fn to_rgb(c: Color) -> Rgb {
+    (c.0 as f64, c.1 as f64, c.2 as f64)
+}
+
+/// Perceptually weighted squared distance
+fn distance((r1, g1, b1): Rgb, (r2, g2, b2): Rgb) -> f64 {
+    2. * (r1 - r2).powi(2) + 4. * (g1 - g2).powi(2) + 3. * (b1 - b2).powi(2)
+}
+
+impl Palette {
+    /// Evenly spaced shades of gray, from black to white
+    pub fn grayscale(n: usize) -> Self {
+        let n = n.max(2);
+        Self(
+            (0..n)
+                .map(|i| {
+                    let v = i * 255 / (n - 1);
+                    Color(v, v, v)
+                })
+                .collect(),
+        )
+    }
+
+    /// The seven inks of color e-paper displays
+    pub fn eink7() -> Self {
+        Self(vec![
+            Color(0, 0, 0),
+            Color(255, 255, 255),
+            Color(0, 255, 0),
+            Color(0, 0, 255),
+            Color(255, 0, 0),
+            Color(255, 255, 0),
+            Color(255, 128, 0),
+        ])
+    }
+
+    /// Read a GIMP palette: one `R G B [name]` entry per line
+    pub fn from_gpl(src: &str) -> io::Result<Self> {
+        let mut s = String::new();
+        File::open(src)?.read_to_string(&mut s)?;
+        let mut colors = Vec::new();
+        for line in s.lines() {
+            let channels = line
+                .split_whitespace()
+                .take(3)
+                .map(|x| x.parse::<usize>())
+                .collect::<Vec<_>>();
+            if let [Ok(r), Ok(g), Ok(b)] = channels[..] {
+                colors.push(Color(r, g, b));
+            }
+        }
+        if colors.is_empty() {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Palette contains no colors",
+            ))
+        } else {
+            Ok(Self(colors))
+        }
+    }
+
+    /// Adaptive palette of at most n colors: split the set of colors along
+    /// its widest channel until there are enough groups, then average each group
+    pub fn median_cut(colors: &[Color], n: usize) -> Self {
+        let mut boxes = vec![colors.iter().map(|&c| to_rgb(c)).collect::<Vec<_>>()];
+        while boxes.len() < n {
+            let spread = |b: &Vec<Rgb>, ch: fn(&Rgb) -> f64| {
+                let lo = b.iter().map(ch).fold(f64::INFINITY, f64::min);
+                let hi = b.iter().map(ch).fold(f64::NEG_INFINITY, f64::max);
+                hi - lo
+            };
+            let channels: [fn(&Rgb) -> f64; 3] = [|c| c.0, |c| c.1, |c| c.2];
+            let mut widest = (0., 0, 0);
+            for (i, b) in boxes.iter().enumerate() {
+                for (j, &ch) in channels.iter().enumerate() {
+                    let s = spread(b, ch);
+                    if s > widest.0 {
+                        widest = (s, i, j);
+                    }
+                }
+            }
+            if widest.0 <= 0. {
+                break;
+            }
+            let (_, i, j) = widest;
+            let mut b = boxes.swap_remove(i);
+            b.sort_by(|x, y| channels[j](x).partial_cmp(&channels[j](y)).unwrap());
+            let upper = b.split_off(b.len() / 2);
+            boxes.push(b);
+            boxes.push(upper);
+        }
+        Self(
+            boxes
+                .into_iter()
+                .filter(|b| !b.is_empty())
+                .map(|b| {
+                    let len = b.len() as f64;
+                    let (r, g, b) = b.iter().fold((0., 0., 0.), |acc, c| {
+                        (acc.0 + c.0, acc.1 + c.1, acc.2 + c.2)
+                    });
+                    Color(
+                        (r / len).round() as usize,
+                        (g / len).round() as usize,
+                        (b / len).round() as usize,
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Keep only the n entries that best represent the given colors
+    pub fn restrict(self, colors: &[Color], n: usize) -> Self {
+        if self.0.len() <= n {
+            return self;
+        }
+        let mut usage = vec![0; self.0.len()];
+        for &c in colors {
+            usage[self.nearest_index(to_rgb(c))] += 1;
+        }
+        let mut ranked = self.0.into_iter().zip(usage).collect::<Vec<_>>();
+        ranked.sort_by_key(|r| std::cmp::Reverse(r.1));
+        Self(ranked.into_iter().take(n.max(1)).map(|(c, _)| c).collect())
+    }
+
+    fn nearest_index(&self, c: Rgb) -> usize {
+        let mut best = (f64::INFINITY, 0);
+        for (i, &p) in self.0.iter().enumerate() {
+            let d = distance(c, to_rgb(p));
+            if d < best.0 {
+                best = (d, i);
+            }
+        }
+        best.1
+    }
+
+    /// Closest color of the palette
+    pub fn nearest(&self, c: Color) -> Color {
+        self.0[self.nearest_index(to_rgb(c))]
+    }
+
+    /// Floyd-Steinberg error diffusion, adapted to tiles.
+    /// Tiles are visited in reading order of their centers and the quantization
+    /// error of each is spread to its closest unvisited neighbors to the right
+    /// and below, with the usual 7/16, 3/16, 5/16, 1/16 ratios.
+    pub fn dither(&self, tiles: &[(Pos, Color)]) -> Vec<Color> {
+        if tiles.is_empty() {
+            return Vec::new();
+        }
+        // Typical distance between neighboring tiles
+        let (mut lo, mut hi) = (tiles[0].0, tiles[0].0);
+        for &(Pos(x, y), _) in tiles {
+            lo = Pos(lo.0.min(x), lo.1.min(y));
+            hi = Pos(hi.0.max(x), hi.1.max(y));
+        }
+        let area = ((hi.0 - lo.0) * (hi.1 - lo.1)).max(1.);
+        let cell = (area / tiles.len() as f64).sqrt();
+        let key = |Pos(x, y): Pos| ((x / cell).floor() as i64, (y / cell).floor() as i64);
+        let mut grid = HashMap::new();
+        for (i, &(p, _)) in tiles.iter().enumerate() {
+            grid.entry(key(p)).or_insert_with(Vec::new).push(i);
+        }
+        let mut order = (0..tiles.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| {
+            let (pa, pb) = (tiles[a].0, tiles[b].0);
+            (key(pa).1, pa.0).partial_cmp(&(key(pb).1, pb.0)).unwrap()
+        });
+        let mut visited = vec![false; tiles.len()];
+        let mut error = vec![(0., 0., 0.); tiles.len()];
+        let mut result = vec![Color(0, 0, 0); tiles.len()];
+        for &i in &order {
+            visited[i] = true;
+            let (p, c) = tiles[i];
+            let c = to_rgb(c);
+            let e = error[i];
+            let wanted = (
+                (c.0 + e.0).clamp(0., 255.),
+                (c.1 + e.1).clamp(0., 255.),
+                (c.2 + e.2).clamp(0., 255.),
+            );
+            let chosen = self.0[self.nearest_index(wanted)];
+            result[i] = chosen;
+            let chosen = to_rgb(chosen);
+            let diff = (
+                wanted.0 - chosen.0,
+                wanted.1 - chosen.1,
+                wanted.2 - chosen.2,
+            );
+            // Closest unvisited neighbor in each direction:
+            // right, below-left, below, below-right
+            let mut slots: [Option<(f64, usize)>; 4] = [None; 4];
+            let (ci, cj) = key(p);
+            for di in -2..=2 {
+                for dj in -1..=2 {
+                    for &j in grid.get(&(ci + di, cj + dj)).into_iter().flatten() {
+                        if visited[j] {
+                            continue;
+                        }
+                        let d = tiles[j].0 - p;
+                        let dist = d.norm();
+                        if dist > cell * 2. {
+                            continue;
+                        }
+                        let angle = d.1.atan2(d.0).to_degrees();
+                        let slot = match angle {
+                            a if (-45. ..22.5).contains(&a) => 0,
+                            a if (22.5..67.5).contains(&a) => 3,
+                            a if (67.5..112.5).contains(&a) => 2,
+                            a if (112.5..157.5).contains(&a) => 1,
+                            _ => continue,
+                        };
+                        if slots[slot].map(|(best, _)| dist < best).unwrap_or(true) {
+                            slots[slot] = Some((dist, j));
+                        }
+                    }
+                }
+            }
+            let ratios = [7., 3., 5., 1.];
+            let total = slots
+                .iter()
+                .zip(ratios.iter())
+                .filter(|(s, _)| s.is_some())
+                .map(|(_, r)| r)
+                .sum::<f64>();
+            for (s, r) in slots.iter().zip(ratios.iter()) {
+                if let Some((_, j)) = s {
+                    let w = r / total;
+                    error[*j].0 += diff.0 * w;
+                    error[*j].1 += diff.1 * w;
+                    error[*j].2 += diff.2 * w;
+                }
+            }
+        }
+        result
+    }
+}
@@ -1,11 +1,12 @@
 use crate::prelude::*;
-use rand::{rngs::ThreadRng, Rng};
+use rand::{rngs::StdRng, Rng};
+use serde_derive::{Deserialize, Serialize};
 use std::cmp::{Eq, PartialEq};
 use std::f64::consts::PI;
 use std::hash::{Hash, Hasher};
 use std::ops::{Add, Mul, Neg, Sub};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Pos(pub f64, pub f64);
 
 impl Pos {
@@ -37,7 +38,7 @@ impl Pos {
         self.0 * other.0 + self.1 * other.1
     }
 
-    pub fn random(f: &Frame, rng: &mut ThreadRng) -> Self {
+    pub fn random(f: &Frame, rng: &mut StdRng) -> Self {
         let errx = f.w as f64 / 10.;
         let erry = f.h as f64 / 10.;
         let x = f.x as f64 - errx + rng.gen::<f64>() * f.w as f64 * 1.2;
@@ -126,7 +127,7 @@ impl Mul<isize> for Pos {
 impl Mul<f64> for Pos {
     type Output = Self;
     fn mul(self, x: f64) -> Self::Output {
-        Pos(self.0 * x as f64, self.1 * x as f64)
+        Pos(self.0 * x, self.1 * x)
     }
 }
 
@@ -1,19 +1,123 @@
+use crate::noise::{Noise, NoiseKind};
 use crate::prelude::*;
-use rand::{rngs::ThreadRng, Rng};
+use rand::{rngs::StdRng, Rng};
+use serde_derive::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct SaltItem {
     pub color: Color,
     pub likeliness: f64,
     pub variability: usize,
+    pub cluster: Option<Cluster>,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Salt(pub Vec<SaltItem>);
 
+/// Shape of groups of salted tiles
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum ClusterMode {
+    /// Round patches
+    Blob,
+    /// Thin lines, all in the same direction
+    Streak,
+}
+
+impl ClusterMode {
+    pub fn from(s: &str) -> Option<Self> {
+        match s {
+            "blob" => Some(ClusterMode::Blob),
+            "streak" => Some(ClusterMode::Streak),
+            _ => None,
+        }
+    }
+}
+
+/// Salt that appears in connected groups of tiles instead of isolated ones.
+/// A tile is salted when a noise field exceeds a threshold, the threshold
+/// being chosen so that the expected proportion of salted tiles is the likeliness.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Cluster {
+    pub mode: ClusterMode,
+    /// Typical size of a group, in tiles
+    pub size: f64,
+    pub noise: Noise,
+    /// Orientation of streaks
+    pub direction: Pos,
+    pub threshold: f64,
+}
+
+/// Number of samples used to estimate the threshold of a cluster
+const THRESHOLD_SAMPLES: usize = 500;
+
+/// Ratio between the period of the field and the size of the groups
+/// that emerge when keeping only its highest values
+const PERIOD: f64 = 3.;
+
+impl Cluster {
+    /// Unseeded cluster, see `seed`
+    pub fn new(mode: ClusterMode, size: f64) -> Self {
+        Self {
+            mode,
+            size,
+            noise: Noise {
+                kind: NoiseKind::Perlin,
+                seed: 0,
+                scale: 1.,
+                octaves: 2,
+            },
+            direction: Pos(1., 0.),
+            threshold: 0.,
+        }
+    }
+
+    /// Pick a new field, scaled for tiles of the given size
+    pub fn seed(mut self, rng: &mut StdRng, tile_size: f64, likeliness: f64) -> Self {
+        self.noise = self.noise.reseed(rng);
+        self.noise.scale = PERIOD * self.size.max(1.) * tile_size;
+        self.direction = Pos::polar(rng.gen_range(0, 360), 1.);
+        // Empirical quantile of the field
+        let mut samples = (0..THRESHOLD_SAMPLES)
+            .map(|_| {
+                let p = Pos(rng.gen::<f64>(), rng.gen::<f64>()) * (self.noise.scale * 50.);
+                self.field(p)
+            })
+            .collect::<Vec<_>>();
+        samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let idx = ((1. - likeliness.clamp(0., 1.)) * THRESHOLD_SAMPLES as f64) as usize;
+        self.threshold = if idx >= THRESHOLD_SAMPLES {
+            f64::INFINITY
+        } else {
+            samples[idx]
+        };
+        self
+    }
+
+    fn field(&self, p: Pos) -> f64 {
+        match self.mode {
+            ClusterMode::Blob => self.noise.sample(p),
+            // Squash the field across the streaks so that groups are `size` times longer than wide
+            ClusterMode::Streak => {
+                let Pos(dx, dy) = self.direction;
+                let along = p.0 * dx + p.1 * dy;
+                let across = p.1 * dx - p.0 * dy;
+                self.noise.sample(Pos(along, across * self.size.max(1.)))
+            }
+        }
+    }
+
+    fn contains(&self, p: Pos) -> bool {
+        self.field(p) > self.threshold
+    }
+}
+
 impl SaltItem {
-    fn sample(&self, rng: &mut ThreadRng) -> Option<Color> {
-        if rng.gen::<f64>() < self.likeliness {
+    fn sample(&self, p: Pos, rng: &mut StdRng) -> Option<Color> {
+        let salted = match &self.cluster {
+            None => rng.gen::<f64>() < self.likeliness,
+            Some(cluster) => cluster.contains(p),
+        };
+        if salted {
             Some(self.color.variate(rng, self.variability))
         } else {
             None
@@ -22,9 +126,9 @@ impl SaltItem {
 }
 
 impl Salt {
-    pub fn sample(&self, rng: &mut ThreadRng) -> Option<Color> {
+    pub fn sample(&self, p: Pos, rng: &mut StdRng) -> Option<Color> {
         for item in self.0.iter() {
-            if let Some(c) = item.sample(rng) {
+            if let Some(c) = item.sample(p, rng) {
                 return Some(c);
             }
         }
@@ -34,4 +138,19 @@ impl Salt {
     pub fn none() -> Self {
         Self(Vec::new())
     }
+
+    /// Give each clustered item its own field
+    pub fn seed(&self, rng: &mut StdRng, tile_size: f64) -> Self {
+        Self(
+            self.0
+                .iter()
+                .map(|item| SaltItem {
+                    cluster: item
+                        .cluster
+                        .map(|c| c.seed(rng, tile_size, item.likeliness)),
+                    ..*item
+                })
+                .collect(),
+        )
+    }
 }
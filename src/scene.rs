@@ -1,36 +1,292 @@
+use crate::blend::Blend;
 use crate::cfg::SceneCfg;
+use crate::feather::{mix, Feather};
+use crate::noise::Noise;
 use crate::pos::crossprod_sign;
 use crate::prelude::*;
-use rand::{rngs::ThreadRng, Rng};
-use std::rc::Rc;
+use crate::svg::Path;
+use rand::{rngs::StdRng, Rng};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 pub struct Scene {
     pub bg: ColorItem,
-    pub items: Vec<Rc<dyn Contains>>,
+    /// Painted instead of `bg` when set
+    pub backdrop: Option<Gradient>,
+    pub items: Vec<Arc<dyn Contains>>,
+    pub feather: Option<Feather>,
+    grid: Grid,
 }
 
 impl Scene {
-    pub fn new(cfg: &SceneCfg, rng: &mut ThreadRng, verbose: Verbosity) -> Self {
+    pub fn new(
+        cfg: &SceneCfg,
+        tiles: &[(Pos, Path)],
+        rng: &mut StdRng,
+        verbose: Verbosity,
+    ) -> Self {
+        let bg = cfg.choose_color(rng);
+        let items = cfg.create_items(rng, tiles, verbose);
+        let backdrop = cfg.background.map(|b| b.create(rng, cfg));
+        let margin = cfg.feather.map(|f| f.margin()).unwrap_or(0.);
         Self {
-            bg: cfg.choose_color(rng),
-            items: cfg.create_items(rng, verbose),
+            bg,
+            backdrop,
+            grid: Grid::new(&items, margin),
+            items,
+            feather: cfg.feather,
+        }
+    }
+
+    /// Replace all items of the scene
+    pub fn set_items(&mut self, items: Vec<Arc<dyn Contains>>) {
+        self.grid = Grid::new(&items, self.feather.map(|f| f.margin()).unwrap_or(0.));
+        self.items = items;
+    }
+
+    /// Color of a position that no item covers
+    fn background(&self, p: Pos, rng: &mut StdRng) -> Color {
+        match &self.backdrop {
+            None => self.bg.sample(p, rng),
+            Some(gradient) => gradient.sample(p, rng),
         }
     }
 
     /// Get color of a position depending on objects that were hit
-    pub fn color(&self, p: Pos, rng: &mut ThreadRng) -> Color {
-        for i in &self.items {
+    pub fn color(&self, p: Pos, rng: &mut StdRng) -> Color {
+        self.color_relief(p, rng).0
+    }
+
+    /// Index of the topmost item that contains a position, None for the background
+    pub fn hit(&self, p: Pos) -> Option<usize> {
+        self.grid.candidates(p).find(|&i| self.items[i].hit(p))
+    }
+
+    /// Same as `color`, also reports the slope of the object that was hit
+    pub fn color_relief(&self, p: Pos, rng: &mut StdRng) -> (Color, Pos) {
+        if let Some(feather) = &self.feather {
+            return self.color_feathered(feather, p, rng);
+        }
+        // Items are sorted from top to bottom: blended items are collected
+        // down to the first item that hides everything below it
+        let mut hits = Vec::new();
+        let mut opaque = None;
+        for i in self.grid.candidates(p) {
+            let i = &self.items[i];
             if let Some(c) = i.contains(p, rng) {
-                return c;
+                match i.blend() {
+                    None => {
+                        opaque = Some((c, i));
+                        break;
+                    }
+                    Some(blend) => hits.push((c, blend, i)),
+                }
             }
         }
-        self.bg.sample(rng)
+        let mut color = match opaque {
+            Some((c, _)) => c,
+            None => self.background(p, rng),
+        };
+        // Paint them starting from the bottom
+        for (c, blend, _) in hits.iter().rev() {
+            color = blend.apply(color, *c);
+        }
+        let relief = hits
+            .first()
+            .map(|&(_, _, i)| i)
+            .or(opaque.map(|(_, i)| i))
+            .map(|i| i.relief(p))
+            .unwrap_or(Pos::zero());
+        (color, relief)
+    }
+
+    /// Same as `color_relief` when items have soft edges
+    fn color_feathered(&self, feather: &Feather, p: Pos, rng: &mut StdRng) -> (Color, Pos) {
+        // Partially visible items from top to bottom, down to the first opaque one
+        let mut hits = Vec::new();
+        let mut opaque = None;
+        for i in self.grid.candidates(p) {
+            let i = &self.items[i];
+            let alpha = feather.opacity(i.as_ref(), p, rng);
+            if alpha <= 0. {
+                continue;
+            }
+            // Outside of the item proper, in its fading edge
+            let c = i
+                .contains(p, rng)
+                .unwrap_or_else(|| i.color().sample(p, rng));
+            if alpha >= 1. && i.blend().is_none() {
+                opaque = Some((c, i));
+                break;
+            }
+            hits.push((c, alpha, i));
+        }
+        let mut color = match opaque {
+            Some((c, _)) => c,
+            None => self.background(p, rng),
+        };
+        for &(c, alpha, i) in hits.iter().rev() {
+            let above = match i.blend() {
+                None => c,
+                Some(blend) => blend.apply(color, c),
+            };
+            color = mix(color, above, alpha);
+        }
+        // Slope of the topmost item that dominates the color
+        let relief = hits
+            .iter()
+            .map(|&(_, alpha, i)| (alpha, i))
+            .chain(opaque.map(|(_, i)| (1., i)))
+            .find(|&(alpha, _)| alpha >= 0.5)
+            .map(|(_, i)| i.relief(p))
+            .unwrap_or(Pos::zero());
+        (color, relief)
     }
 }
 
 /// Trait for anything that can contain a 2D point
-pub trait Contains: std::fmt::Display {
-    fn contains(&self, p: Pos, rng: &mut ThreadRng) -> Option<Color>;
+pub trait Contains: std::fmt::Display + Send + Sync {
+    /// Whether the item covers a point
+    fn hit(&self, p: Pos) -> bool;
+
+    fn color(&self) -> &ColorItem;
+
+    fn contains(&self, p: Pos, rng: &mut StdRng) -> Option<Color> {
+        if self.hit(p) {
+            Some(self.color().sample(p, rng))
+        } else {
+            None
+        }
+    }
+
+    /// Slope of the virtual surface of the item at a point it contains (flat by default)
+    fn relief(&self, _p: Pos) -> Pos {
+        Pos::zero()
+    }
+
+    /// Lower and upper corners of a box outside of which `contains` never hits,
+    /// None if the item is unbounded
+    fn bbox(&self) -> Option<(Pos, Pos)> {
+        None
+    }
+
+    /// Signed distance from a point to the edge of the item, positive inside,
+    /// None if unknown (the item then keeps a hard edge even when feathered)
+    fn edge(&self, _p: Pos) -> Option<f64> {
+        None
+    }
+
+    /// How the item is painted over the items below it, None if it hides them
+    fn blend(&self) -> Option<Blend> {
+        None
+    }
+}
+
+/// Uniform grid over the bounding boxes of the items of a scene,
+/// so that each point is only tested against the items that may contain it
+struct Grid {
+    origin: Pos,
+    cell: f64,
+    cols: usize,
+    rows: usize,
+    /// Indices of bounded items that overlap each cell, in increasing order
+    cells: Vec<Vec<usize>>,
+    /// Indices of items that may be hit anywhere, in increasing order
+    unbounded: Vec<usize>,
+}
+
+/// Average number of cells per bounded item
+const CELLS_PER_ITEM: f64 = 4.;
+/// Maximum number of cells along each axis
+const MAX_CELLS: usize = 256;
+
+impl Grid {
+    /// Boxes are enlarged by `margin` on all sides
+    fn new(items: &[Arc<dyn Contains>], margin: f64) -> Self {
+        let mut unbounded = Vec::new();
+        let mut boxes = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            match item.bbox() {
+                None => unbounded.push(i),
+                Some((a, b)) => boxes.push((i, (a - Pos(margin, margin), b + Pos(margin, margin)))),
+            }
+        }
+        let mut lo = Pos(f64::INFINITY, f64::INFINITY);
+        let mut hi = Pos(f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for &(_, (a, b)) in &boxes {
+            lo = Pos(lo.0.min(a.0), lo.1.min(a.1));
+            hi = Pos(hi.0.max(b.0), hi.1.max(b.1));
+        }
+        let (w, h) = ((hi.0 - lo.0).max(1.), (hi.1 - lo.1).max(1.));
+        let cell = (w * h / (boxes.len() as f64 * CELLS_PER_ITEM).max(1.)).sqrt();
+        let cell = cell.max(w.max(h) / MAX_CELLS as f64);
+        let cols = (w / cell).ceil() as usize;
+        let rows = (h / cell).ceil() as usize;
+        let mut grid = Self {
+            origin: lo,
+            cell,
+            cols,
+            rows,
+            cells: vec![Vec::new(); if boxes.is_empty() { 0 } else { cols * rows }],
+            unbounded,
+        };
+        for (i, (a, b)) in boxes {
+            let (i0, j0) = grid.clamped(a);
+            let (i1, j1) = grid.clamped(b);
+            for j in j0..=j1 {
+                for k in i0..=i1 {
+                    grid.cells[j * cols + k].push(i);
+                }
+            }
+        }
+        grid
+    }
+
+    /// Cell of a point, clamped to the grid
+    fn clamped(&self, p: Pos) -> (usize, usize) {
+        let i = ((p.0 - self.origin.0) / self.cell).floor().max(0.) as usize;
+        let j = ((p.1 - self.origin.1) / self.cell).floor().max(0.) as usize;
+        (i.min(self.cols - 1), j.min(self.rows - 1))
+    }
+
+    /// Indices of all items that may contain p, in the same order as the items of the scene
+    fn candidates(&self, p: Pos) -> impl Iterator<Item = usize> + '_ {
+        let i = ((p.0 - self.origin.0) / self.cell).floor();
+        let j = ((p.1 - self.origin.1) / self.cell).floor();
+        let inside = !self.cells.is_empty()
+            && i >= 0.
+            && j >= 0.
+            && (i as usize) < self.cols
+            && (j as usize) < self.rows;
+        let bounded: &[usize] = if inside {
+            &self.cells[j as usize * self.cols + i as usize]
+        } else {
+            &[]
+        };
+        Merge {
+            a: bounded.iter().copied().peekable(),
+            b: self.unbounded.iter().copied().peekable(),
+        }
+    }
+}
+
+/// Merge of two increasing sequences
+struct Merge<A: Iterator<Item = usize>, B: Iterator<Item = usize>> {
+    a: std::iter::Peekable<A>,
+    b: std::iter::Peekable<B>,
+}
+
+impl<A: Iterator<Item = usize>, B: Iterator<Item = usize>> Iterator for Merge<A, B> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        match (self.a.peek(), self.b.peek()) {
+            (Some(x), Some(y)) if x < y => self.a.next(),
+            (Some(_), None) => self.a.next(),
+            _ => self.b.next(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -40,14 +296,17 @@ pub struct ColorItem {
     pub theme: Color,
     pub distance: usize,
     pub salt: Salt,
+    pub noise: Option<Noise>,
 }
 
 impl ColorItem {
-    pub fn sample(&self, rng: &mut ThreadRng) -> Color {
-        self.salt.sample(rng).unwrap_or_else(|| {
-            self.shade
-                .meanpoint(self.theme, self.distance)
-                .variate(rng, self.deviation)
+    pub fn sample(&self, p: Pos, rng: &mut StdRng) -> Color {
+        self.salt.sample(p, rng).unwrap_or_else(|| {
+            let c = self.shade.meanpoint(self.theme, self.distance);
+            match &self.noise {
+                None => c.variate(rng, self.deviation),
+                Some(noise) => c.variate_field(noise, p, self.deviation),
+            }
         })
     }
 }
@@ -60,7 +319,7 @@ pub struct Disc {
 }
 
 impl Disc {
-    pub fn random(rng: &mut ThreadRng, f: &Frame, color: ColorItem, size_hint: f64) -> Self {
+    pub fn random(rng: &mut StdRng, f: &Frame, color: ColorItem, size_hint: f64) -> Self {
         let center = Pos::random(f, rng);
         let radius = (rng.gen::<f64>() * size_hint + 0.1) * (f.h.min(f.w) as f64);
         Self {
@@ -72,12 +331,26 @@ impl Disc {
 }
 
 impl Contains for Disc {
-    fn contains(&self, p: Pos, rng: &mut ThreadRng) -> Option<Color> {
-        if (self.center - p).dot_self() < self.radius.powi(2) {
-            Some(self.color.sample(rng))
-        } else {
-            None
-        }
+    fn hit(&self, p: Pos) -> bool {
+        (self.center - p).dot_self() < self.radius.powi(2)
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.color
+    }
+
+    /// Dome: flat at the center, steep near the edges
+    fn relief(&self, p: Pos) -> Pos {
+        (self.center - p) * (2. / self.radius)
+    }
+
+    fn bbox(&self) -> Option<(Pos, Pos)> {
+        let r = Pos(self.radius, self.radius);
+        Some((self.center - r, self.center + r))
+    }
+
+    fn edge(&self, p: Pos) -> Option<f64> {
+        Some(self.radius - (p - self.center).norm())
     }
 }
 
@@ -90,7 +363,7 @@ pub struct HalfPlane {
 
 impl HalfPlane {
     pub fn random(
-        rng: &mut ThreadRng,
+        rng: &mut StdRng,
         limit: Pos,
         indic: isize,
         var: usize,
@@ -109,13 +382,17 @@ impl HalfPlane {
 }
 
 impl Contains for HalfPlane {
-    fn contains(&self, p: Pos, rng: &mut ThreadRng) -> Option<Color> {
+    fn hit(&self, p: Pos) -> bool {
         let dotprod = (p - self.limit).dot(self.reference - self.limit);
-        if dotprod < 0. {
-            Some(self.color.sample(rng))
-        } else {
-            None
-        }
+        dotprod < 0.
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.color
+    }
+
+    fn edge(&self, p: Pos) -> Option<f64> {
+        Some(-(p - self.limit).dot((self.reference - self.limit).unit()))
     }
 }
 
@@ -128,7 +405,7 @@ pub struct Triangle {
 }
 
 impl Triangle {
-    pub fn random(rng: &mut ThreadRng, circ: Disc) -> Self {
+    pub fn random(rng: &mut StdRng, circ: Disc) -> Self {
         let theta0 = rng.gen_range(0, 360);
         let theta1 = rng.gen_range(80, 150);
         let theta2 = rng.gen_range(80, 150);
@@ -142,17 +419,39 @@ impl Triangle {
 }
 
 impl Contains for Triangle {
-    fn contains(&self, p: Pos, rng: &mut ThreadRng) -> Option<Color> {
+    fn hit(&self, p: Pos) -> bool {
         let d1 = crossprod_sign(p, self.a, self.b);
         let d2 = crossprod_sign(p, self.b, self.c);
         let d3 = crossprod_sign(p, self.c, self.a);
         let has_pos = d1 || d2 || d3;
         let has_neg = !(d1 && d2 && d3);
-        if !(has_neg && has_pos) {
-            Some(self.color.sample(rng))
-        } else {
-            None
+        !(has_neg && has_pos)
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.color
+    }
+
+    /// Pyramid: each point slopes down towards the nearest side
+    fn relief(&self, p: Pos) -> Pos {
+        let center = (self.a + self.b + self.c) * (1. / 3.);
+        let mut nearest = (f64::INFINITY, Pos::zero());
+        for &(u, v) in &[(self.a, self.b), (self.b, self.c), (self.c, self.a)] {
+            let dist = ((p - u) - (p - u).project(v - u)).norm();
+            if dist < nearest.0 {
+                let outwards = (u - center) - (u - center).project(v - u);
+                nearest = (dist, -outwards.unit());
+            }
         }
+        nearest.1
+    }
+
+    fn bbox(&self) -> Option<(Pos, Pos)> {
+        let (a, b, c) = (self.a, self.b, self.c);
+        Some((
+            Pos(a.0.min(b.0).min(c.0), a.1.min(b.1).min(c.1)),
+            Pos(a.0.max(b.0).max(c.0), a.1.max(b.1).max(c.1)),
+        ))
     }
 }
 
@@ -166,7 +465,7 @@ pub struct Spiral {
 
 impl Spiral {
     pub fn random(
-        rng: &mut ThreadRng,
+        rng: &mut StdRng,
         f: &Frame,
         color: ColorItem,
         width: f64,
@@ -192,15 +491,15 @@ impl FracPart for f64 {
 }
 
 impl Contains for Spiral {
-    fn contains(&self, p: Pos, rng: &mut ThreadRng) -> Option<Color> {
+    fn hit(&self, p: Pos) -> bool {
         let Pos(di, dj) = self.center - p;
         let theta = di.atan2(dj);
         let radius = (di.powi(2) + dj.powi(2)).sqrt() + theta / std::f64::consts::PI * self.width;
-        if (radius / self.width).frac_part() < self.tightness {
-            Some(self.color.sample(rng))
-        } else {
-            None
-        }
+        (radius / self.width).frac_part() < self.tightness
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.color
     }
 }
 
@@ -212,7 +511,7 @@ pub struct Stripe {
 }
 
 impl Stripe {
-    pub fn random(rng: &mut ThreadRng, f: &Frame, color: ColorItem, width: f64) -> Self {
+    pub fn random(rng: &mut StdRng, f: &Frame, color: ColorItem, width: f64) -> Self {
         let limit = Pos::random(f, rng);
         let reference = limit + Pos::polar(rng.gen_range(0, 360), width);
         Self {
@@ -224,14 +523,29 @@ impl Stripe {
 }
 
 impl Contains for Stripe {
-    fn contains(&self, p: Pos, rng: &mut ThreadRng) -> Option<Color> {
+    fn hit(&self, p: Pos) -> bool {
         let dotprod1 = (p - self.limit).dot(self.reference - self.limit);
         let dotprod2 = (p - self.reference).dot(self.limit - self.reference);
-        if dotprod1 > 0. && dotprod2 > 0. {
-            Some(self.color.sample(rng))
-        } else {
-            None
-        }
+        dotprod1 > 0. && dotprod2 > 0.
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.color
+    }
+
+    /// Rounded ridge running along the middle of the stripe
+    fn relief(&self, p: Pos) -> Pos {
+        let across = self.reference - self.limit;
+        let t = (p - self.limit).dot(across) / across.dot_self();
+        across.unit() * (2. - 4. * t)
+    }
+
+    /// Distance to the nearest of the two sides
+    fn edge(&self, p: Pos) -> Option<f64> {
+        let across = (self.reference - self.limit).unit();
+        let d1 = (p - self.limit).dot(across);
+        let d2 = (self.reference - p).dot(across);
+        Some(d1.min(d2))
     }
 }
 
@@ -246,7 +560,7 @@ pub struct Wave {
 
 impl Wave {
     pub fn random(
-        _rng: &mut ThreadRng,
+        _rng: &mut StdRng,
         limit: Pos,
         indic: isize,
         width: f64,
@@ -264,16 +578,15 @@ impl Wave {
 }
 
 impl Contains for Wave {
-    fn contains(&self, p: Pos, rng: &mut ThreadRng) -> Option<Color> {
+    fn hit(&self, p: Pos) -> bool {
         let proj = (p - self.limit).project(self.reference - self.limit);
         let nearpt = p - proj;
         let phase = (self.limit - nearpt).norm() * self.frequency;
-        if phase.cos() * self.amplitude > (p - self.limit).dot((self.reference - self.limit).unit())
-        {
-            Some(self.color.sample(rng))
-        } else {
-            None
-        }
+        phase.cos() * self.amplitude > (p - self.limit).dot((self.reference - self.limit).unit())
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.color
     }
 }
 
@@ -288,7 +601,7 @@ pub struct Sawtooth {
 
 impl Sawtooth {
     pub fn random(
-        _rng: &mut ThreadRng,
+        _rng: &mut StdRng,
         limit: Pos,
         indic: isize,
         width: f64,
@@ -306,7 +619,7 @@ impl Sawtooth {
 }
 
 impl Contains for Sawtooth {
-    fn contains(&self, p: Pos, rng: &mut ThreadRng) -> Option<Color> {
+    fn hit(&self, p: Pos) -> bool {
         let sawtooth = |f: f64| {
             let int = f.floor();
             let frac = f - int;
@@ -321,12 +634,741 @@ impl Contains for Sawtooth {
         let proj = (p - self.limit).project(self.reference - self.limit);
         let nearpt = p - proj;
         let phase = (self.limit - nearpt).norm() * self.frequency;
-        if sawtooth(phase) * self.amplitude
+        sawtooth(phase) * self.amplitude
             > (p - self.limit).dot((self.reference - self.limit).unit())
-        {
-            Some(self.color.sample(rng))
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.color
+    }
+}
+
+/// Distance used to find the nearest site of a Voronoi diagram
+#[derive(Debug, Clone, Copy)]
+pub enum Metric {
+    Euclidean,
+    Manhattan,
+    Chebyshev,
+}
+
+impl Metric {
+    pub fn from(s: &str) -> Option<Self> {
+        match s {
+            "euclidean" => Some(Metric::Euclidean),
+            "manhattan" => Some(Metric::Manhattan),
+            "chebyshev" => Some(Metric::Chebyshev),
+            _ => None,
+        }
+    }
+
+    fn dist(self, a: Pos, b: Pos) -> f64 {
+        let Pos(dx, dy) = a - b;
+        match self {
+            Metric::Euclidean => (dx * dx + dy * dy).sqrt(),
+            Metric::Manhattan => dx.abs() + dy.abs(),
+            Metric::Chebyshev => dx.abs().max(dy.abs()),
+        }
+    }
+}
+
+/// Data shared by several items, that tells which of them covers a point.
+/// Each item covers the points that are mapped to its own index.
+pub trait Partition: std::fmt::Debug + Send + Sync {
+    fn index(&self, p: Pos) -> Option<usize>;
+}
+
+/// Source of the identifiers of shared partitions
+static SHARED_IDS: AtomicUsize = AtomicUsize::new(0);
+
+/// Identifier of a partition, exact coordinates of a point and index of that point
+type Resolved = (usize, (u64, u64), Option<usize>);
+
+thread_local! {
+    /// Last point resolved on this thread
+    static LAST_RESOLVED: std::cell::Cell<Option<Resolved>> = const { std::cell::Cell::new(None) };
+}
+
+/// Partition shared by several items.
+/// The items are tested one after the other on the same point,
+/// so the last point resolved is remembered and the index is only computed once.
+#[derive(Debug)]
+pub struct Shared<T> {
+    pub inner: T,
+    id: usize,
+}
+
+impl<T: Partition> Shared<T> {
+    pub fn new(inner: T) -> Arc<Self> {
+        Arc::new(Self {
+            inner,
+            id: SHARED_IDS.fetch_add(1, Ordering::Relaxed),
+        })
+    }
+
+    pub fn index(&self, p: Pos) -> Option<usize> {
+        let key = (self.id, (p.0.to_bits(), p.1.to_bits()));
+        LAST_RESOLVED.with(|last| match last.get() {
+            Some((id, q, index)) if (id, q) == key => index,
+            _ => {
+                let index = self.inner.index(p);
+                last.set(Some((key.0, key.1, index)));
+                index
+            }
+        })
+    }
+}
+
+/// Seed points of a Voronoi diagram, shared by all of its regions
+#[derive(Debug)]
+pub struct Sites {
+    pub metric: Metric,
+    pub centers: Vec<Pos>,
+    /// Radius of each site, all zero for a plain Voronoi diagram
+    /// and a power diagram otherwise
+    pub weights: Vec<f64>,
+}
+
+impl Sites {
+    /// Index of the site closest to a point
+    fn nearest(&self, p: Pos) -> usize {
+        let mut best = (f64::INFINITY, 0);
+        for (i, (&c, &w)) in self.centers.iter().zip(self.weights.iter()).enumerate() {
+            let d = self.metric.dist(p, c).powi(2) - w.powi(2);
+            if d < best.0 {
+                best = (d, i);
+            }
+        }
+        best.1
+    }
+}
+
+impl Partition for Sites {
+    fn index(&self, p: Pos) -> Option<usize> {
+        Some(self.nearest(p))
+    }
+}
+
+/// Region of a Voronoi diagram: all points closer to its site than to any other
+#[derive(Debug)]
+pub struct Voronoi {
+    pub sites: Arc<Shared<Sites>>,
+    pub index: usize,
+    pub color: ColorItem,
+}
+
+impl Contains for Voronoi {
+    fn hit(&self, p: Pos) -> bool {
+        self.sites.index(p) == Some(self.index)
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.color
+    }
+}
+
+/// Area of a noise height field that lies above a given elevation
+#[derive(Debug)]
+pub struct Contour {
+    pub noise: Noise,
+    pub level: f64,
+    /// Flat steps instead of continuous slopes
+    pub terrace: bool,
+    pub color: ColorItem,
+}
+
+impl Contains for Contour {
+    fn hit(&self, p: Pos) -> bool {
+        self.noise.sample(p) >= self.level
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.color
+    }
+
+    /// Uphill direction of the height field
+    fn relief(&self, p: Pos) -> Pos {
+        if self.terrace {
+            return Pos::zero();
+        }
+        let h = self.noise.scale * 0.01;
+        let dx = self.noise.sample(p + Pos(h, 0.)) - self.noise.sample(p - Pos(h, 0.));
+        let dy = self.noise.sample(p + Pos(0., h)) - self.noise.sample(p - Pos(0., h));
+        Pos(dx, dy) * (self.noise.scale / (2. * h))
+    }
+}
+
+/// Regular polygon, or star polygon {sides/step} when step > 1
+#[derive(Debug)]
+pub struct Polygon {
+    pub center: Pos,
+    pub radius: f64,
+    pub sides: usize,
+    /// Number of vertices skipped by each edge
+    pub step: usize,
+    /// Angle of the first vertex, in degrees
+    pub rotation: isize,
+    pub color: ColorItem,
+    vertices: Vec<Pos>,
+}
+
+impl Polygon {
+    pub fn new(
+        center: Pos,
+        radius: f64,
+        sides: usize,
+        step: usize,
+        rotation: isize,
+        color: ColorItem,
+    ) -> Self {
+        let sides = sides.max(3);
+        let vertices = (0..sides)
+            .map(|k| {
+                let theta = radians(rotation)
+                    + std::f64::consts::PI * 2. * (k * step) as f64 / sides as f64;
+                center + Pos(theta.cos(), theta.sin()) * radius
+            })
+            .collect();
+        Self {
+            center,
+            radius,
+            sides,
+            step,
+            rotation,
+            color,
+            vertices,
+        }
+    }
+}
+
+impl Contains for Polygon {
+    /// Nonzero winding rule, so that the center of stars is filled
+    fn hit(&self, p: Pos) -> bool {
+        if (p - self.center).dot_self() > self.radius.powi(2) {
+            return false;
+        }
+        let mut winding = 0;
+        for i in 0..self.vertices.len() {
+            let (a, b) = (
+                self.vertices[i],
+                self.vertices[(i + 1) % self.vertices.len()],
+            );
+            let side = (b.0 - a.0) * (p.1 - a.1) - (p.0 - a.0) * (b.1 - a.1);
+            if a.1 <= p.1 && b.1 > p.1 && side > 0. {
+                winding += 1;
+            } else if a.1 > p.1 && b.1 <= p.1 && side < 0. {
+                winding -= 1;
+            }
+        }
+        winding != 0
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.color
+    }
+
+    fn bbox(&self) -> Option<(Pos, Pos)> {
+        let r = Pos(self.radius, self.radius);
+        Some((self.center - r, self.center + r))
+    }
+}
+
+/// Angular sector around a center, optionally curving with the distance to the center
+#[derive(Debug)]
+pub struct Sector {
+    pub center: Pos,
+    /// Angle at which the sector starts, in radians
+    pub start: f64,
+    /// Angular width, in radians
+    pub width: f64,
+    /// Rotation of the sector per unit of distance to the center, in radians
+    pub twist: f64,
+    pub color: ColorItem,
+}
+
+impl Contains for Sector {
+    fn hit(&self, p: Pos) -> bool {
+        let Pos(dx, dy) = p - self.center;
+        let theta = dy.atan2(dx) - self.twist * (dx * dx + dy * dy).sqrt();
+        (theta - self.start).rem_euclid(std::f64::consts::PI * 2.) < self.width
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.color
+    }
+}
+
+/// Part of a ring between two angles
+#[derive(Debug)]
+pub struct Annulus {
+    pub center: Pos,
+    pub inner: f64,
+    pub outer: f64,
+    /// Angle at which the segment starts, in radians
+    pub start: f64,
+    /// Angular length, in radians
+    pub width: f64,
+    pub color: ColorItem,
+}
+
+impl Annulus {
+    /// Angle of a point measured from the start of the segment, within [0; 2*PI)
+    fn angle(&self, p: Pos) -> f64 {
+        let Pos(dx, dy) = p - self.center;
+        (dy.atan2(dx) - self.start).rem_euclid(std::f64::consts::PI * 2.)
+    }
+}
+
+impl Contains for Annulus {
+    fn hit(&self, p: Pos) -> bool {
+        let r = (p - self.center).norm();
+        self.inner <= r && r < self.outer && self.angle(p) < self.width
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.color
+    }
+
+    /// Rounded like a pipe bent around the center
+    fn relief(&self, p: Pos) -> Pos {
+        let across = self.outer - self.inner;
+        let r = (p - self.center).norm();
+        let t = (r - self.inner) / across;
+        (p - self.center).unit() * (2. - 4. * t)
+    }
+
+    fn bbox(&self) -> Option<(Pos, Pos)> {
+        let r = Pos(self.outer, self.outer);
+        Some((self.center - r, self.center + r))
+    }
+
+    /// Distance to the nearest of the two circles or of the two ends
+    fn edge(&self, p: Pos) -> Option<f64> {
+        let r = (p - self.center).norm();
+        let radial = (r - self.inner).min(self.outer - r);
+        let theta = self.angle(p);
+        let angular = if theta < self.width {
+            theta.min(self.width - theta)
         } else {
-            None
+            // Outside of the segment, measured towards the nearest end
+            -(theta - self.width).min(std::f64::consts::PI * 2. - theta)
+        };
+        Some(radial.min(angular * r))
+    }
+}
+
+/// Equation that delimits a curve item
+#[derive(Debug, Clone, Copy)]
+pub enum CurveKind {
+    /// Polar rose r = cos(n/d * theta)
+    Rose { n: usize, d: usize },
+    /// Band around the parametric curve (sin(a*t + phase), ratio * sin(b*t))
+    Lissajous {
+        a: usize,
+        b: usize,
+        phase: f64,
+        ratio: f64,
+        /// Thickness of the band
+        width: f64,
+    },
+    /// Gielis superformula, with both axes of equal length
+    Superformula { m: f64, n1: f64, n2: f64, n3: f64 },
+}
+
+/// Region delimited by a polar or parametric curve
+#[derive(Debug)]
+pub struct Curve {
+    pub center: Pos,
+    pub size: f64,
+    /// In degrees
+    pub rotation: isize,
+    pub kind: CurveKind,
+    pub color: ColorItem,
+    /// Points along the curve, only for those that are not polar
+    path: Vec<Pos>,
+}
+
+/// Number of points per period used to approximate parametric curves
+const CURVE_SAMPLES: usize = 64;
+
+impl Curve {
+    pub fn new(center: Pos, size: f64, rotation: isize, kind: CurveKind, color: ColorItem) -> Self {
+        let path = match kind {
+            CurveKind::Lissajous {
+                a, b, phase, ratio, ..
+            } => {
+                let n = CURVE_SAMPLES * a.max(b).max(1);
+                (0..=n)
+                    .map(|i| {
+                        let t = std::f64::consts::PI * 2. * i as f64 / n as f64;
+                        Pos((a as f64 * t + phase).sin(), ratio * (b as f64 * t).sin()) * size
+                    })
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        Self {
+            center,
+            size,
+            rotation,
+            kind,
+            color,
+            path,
         }
     }
+
+    /// Coordinates of a point relative to the center and orientation of the curve
+    fn local(&self, p: Pos) -> Pos {
+        let (s, c) = radians(self.rotation).sin_cos();
+        let Pos(x, y) = p - self.center;
+        Pos(x * c + y * s, y * c - x * s)
+    }
+}
+
+impl Contains for Curve {
+    fn hit(&self, p: Pos) -> bool {
+        let q = self.local(p);
+        let Pos(x, y) = q;
+        let radius = (x * x + y * y).sqrt() / self.size;
+        let theta = y.atan2(x);
+        match self.kind {
+            CurveKind::Rose { n, d } => {
+                let k = n as f64 / d.max(1) as f64;
+                // Negative values of the equation are drawn on the opposite side
+                (0..2 * d.max(1)).any(|j| {
+                    let sign = if j % 2 == 0 { 1. } else { -1. };
+                    sign * (k * (theta + std::f64::consts::PI * j as f64)).cos() >= radius
+                })
+            }
+            CurveKind::Superformula { m, n1, n2, n3 } => {
+                let t = m * theta / 4.;
+                let r = (t.cos().abs().powf(n2) + t.sin().abs().powf(n3)).powf(-1. / n1);
+                radius <= r
+            }
+            CurveKind::Lissajous { width, .. } => {
+                if x.abs() > self.size + width || y.abs() > self.size + width {
+                    return false;
+                }
+                self.path.windows(2).any(|w| {
+                    let (a, b) = (w[0], w[1]);
+                    let t = ((q - a).dot(b - a) / (b - a).dot_self().max(1e-9)).clamp(0., 1.);
+                    (q - (a + (b - a) * t)).norm() < width / 2.
+                })
+            }
+        }
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.color
+    }
+
+    fn bbox(&self) -> Option<(Pos, Pos)> {
+        let reach = match self.kind {
+            CurveKind::Rose { .. } => self.size,
+            CurveKind::Lissajous { ratio, width, .. } => {
+                self.size * (1. + ratio * ratio).sqrt() + width / 2.
+            }
+            // The superformula has no simple bound
+            CurveKind::Superformula { .. } => return None,
+        };
+        let r = Pos(reach, reach);
+        Some((self.center - r, self.center + r))
+    }
+}
+
+/// Smooth flow whose streamlines are the level sets of a stream function
+#[derive(Debug)]
+pub enum Flow {
+    /// Curl of a noise field
+    Noise(Noise),
+    /// Sum of point vortices, each with a center and a strength
+    Vortices(Vec<(Pos, f64)>),
+}
+
+impl Flow {
+    /// Value of the stream function at a point
+    fn stream(&self, p: Pos) -> f64 {
+        match self {
+            Flow::Noise(noise) => noise.sample(p),
+            Flow::Vortices(v) => v
+                .iter()
+                .map(|&(c, s)| s * (c - p).norm().max(1.).ln())
+                .sum(),
+        }
+    }
+}
+
+/// Flow cut into bands between streamlines, shared by all of its bands
+#[derive(Debug)]
+pub struct Streamlines {
+    pub flow: Flow,
+    /// Number of bands before they repeat
+    pub bands: usize,
+    /// Difference of the stream function across a band
+    pub width: f64,
+}
+
+impl Partition for Streamlines {
+    fn index(&self, p: Pos) -> Option<usize> {
+        let k = (self.flow.stream(p) / self.width).floor() as i64;
+        Some(k.rem_euclid(self.bands.max(1) as i64) as usize)
+    }
+}
+
+/// Band between two streamlines, repeating every `bands` bands
+#[derive(Debug)]
+pub struct Stream {
+    pub field: Arc<Shared<Streamlines>>,
+    pub band: usize,
+    pub color: ColorItem,
+}
+
+impl Contains for Stream {
+    fn hit(&self, p: Pos) -> bool {
+        self.field.index(p) == Some(self.band)
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.color
+    }
+}
+
+/// Window of the complex plane over which a Julia or Mandelbrot set is iterated
+#[derive(Debug)]
+pub struct EscapeTime {
+    /// Constant of the Julia set, None for the Mandelbrot set
+    pub julia: Option<Pos>,
+    /// Point of the frame that shows the center of the window
+    pub origin: Pos,
+    /// Complex number at the center of the window
+    pub center: Pos,
+    /// Size of a pixel in the complex plane
+    pub unit: f64,
+    pub iterations: usize,
+    /// Number of bands before they repeat
+    pub bands: usize,
+}
+
+impl EscapeTime {
+    /// Number of iterations before escaping, None for points inside the set
+    fn escape(&self, p: Pos) -> Option<usize> {
+        let c0 = self.center + (p - self.origin) * self.unit;
+        let (mut z, c) = match self.julia {
+            Some(c) => (c0, c),
+            None => (Pos::zero(), c0),
+        };
+        for n in 0..self.iterations {
+            if z.dot_self() > 4. {
+                return Some(n);
+            }
+            z = Pos(z.0 * z.0 - z.1 * z.1, 2. * z.0 * z.1) + c;
+        }
+        None
+    }
+}
+
+impl Partition for EscapeTime {
+    fn index(&self, p: Pos) -> Option<usize> {
+        self.escape(p).map(|n| n % self.bands.max(1))
+    }
+}
+
+/// Points that escape after a number of iterations that falls in a given band,
+/// bands repeating every `bands` iterations.
+/// Points inside the set never escape and belong to no band:
+/// they fall through to whatever lies below, usually the background.
+#[derive(Debug)]
+pub struct Escape {
+    pub fractal: Arc<Shared<EscapeTime>>,
+    pub band: usize,
+    pub color: ColorItem,
+}
+
+impl Contains for Escape {
+    fn hit(&self, p: Pos) -> bool {
+        self.fractal.index(p) == Some(self.band)
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.color
+    }
+}
+
+/// Shape of the lines along which a gradient keeps the same color
+#[derive(Debug, Clone, Copy)]
+pub enum GradientShape {
+    Linear,
+    Radial,
+}
+
+impl GradientShape {
+    pub fn from(s: &str) -> Option<Self> {
+        match s {
+            "linear" => Some(GradientShape::Linear),
+            "radial" => Some(GradientShape::Radial),
+            _ => None,
+        }
+    }
+}
+
+/// Sweep through several colors, covering the whole plane
+#[derive(Debug, Clone)]
+pub struct Gradient {
+    pub shape: GradientShape,
+    /// Where the first color is
+    pub start: Pos,
+    /// Where the last color is, along the axis or on the outer circle
+    pub end: Pos,
+    /// Flat bands of colors instead of a smooth transition
+    pub banded: bool,
+    /// Irregularity of the boundaries between colors, as a proportion of a band
+    pub jitter: f64,
+    pub colors: Vec<ColorItem>,
+}
+
+impl Gradient {
+    /// Position of a point along the gradient, 0 at the start and 1 at the end
+    fn progress(&self, p: Pos) -> f64 {
+        let axis = self.end - self.start;
+        let len = axis.dot_self().max(1e-9);
+        match self.shape {
+            GradientShape::Linear => (p - self.start).dot(axis) / len,
+            GradientShape::Radial => (p - self.start).dot_self().sqrt() / len.sqrt(),
+        }
+    }
+
+    pub fn sample(&self, p: Pos, rng: &mut StdRng) -> Color {
+        let n = self.colors.len();
+        if n <= 1 {
+            return self.colors[0].sample(p, rng);
+        }
+        let t = self.progress(p) + self.jitter * (rng.gen::<f64>() - 0.5) / n as f64;
+        if self.banded {
+            let i = ((t * n as f64).max(0.) as usize).min(n - 1);
+            self.colors[i].sample(p, rng)
+        } else {
+            let x = t.clamp(0., 1.) * (n - 1) as f64;
+            let i = (x as usize).min(n - 2);
+            let below = self.colors[i].sample(p, rng);
+            let above = self.colors[i + 1].sample(p, rng);
+            mix(below, above, x - i as f64)
+        }
+    }
+}
+
+impl Contains for Gradient {
+    fn hit(&self, _p: Pos) -> bool {
+        true
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.colors[0]
+    }
+
+    fn contains(&self, p: Pos, rng: &mut StdRng) -> Option<Color> {
+        Some(self.sample(p, rng))
+    }
+}
+
+/// Final states of a cellular automaton that ran on the tiles,
+/// each cell being known by the center of its tile
+#[derive(Debug)]
+pub struct Automaton {
+    pub cells: Vec<(Pos, usize)>,
+    /// Side of the squares into which cells are sorted
+    size: f64,
+    buckets: HashMap<(i64, i64), Vec<usize>>,
+    /// Lowest and highest squares that hold cells
+    extent: ((i64, i64), (i64, i64)),
+}
+
+impl Automaton {
+    pub fn new(cells: Vec<(Pos, usize)>) -> Self {
+        let mut lo = Pos(f64::INFINITY, f64::INFINITY);
+        let mut hi = Pos(f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for &(Pos(x, y), _) in &cells {
+            lo = Pos(lo.0.min(x), lo.1.min(y));
+            hi = Pos(hi.0.max(x), hi.1.max(y));
+        }
+        // About one cell per square
+        let area = ((hi.0 - lo.0) * (hi.1 - lo.1)).max(1.);
+        let size = (area / cells.len().max(1) as f64).sqrt().max(1.);
+        let mut buckets = HashMap::new();
+        for (i, &(p, _)) in cells.iter().enumerate() {
+            buckets
+                .entry(Self::bucket(p, size))
+                .or_insert_with(Vec::new)
+                .push(i);
+        }
+        Self {
+            extent: (Self::bucket(lo, size), Self::bucket(hi, size)),
+            cells,
+            size,
+            buckets,
+        }
+    }
+
+    fn bucket(Pos(x, y): Pos, size: f64) -> (i64, i64) {
+        ((x / size).floor() as i64, (y / size).floor() as i64)
+    }
+
+    /// State of the cell whose center is nearest to a point
+    pub fn state(&self, p: Pos) -> Option<usize> {
+        if self.cells.is_empty() {
+            return None;
+        }
+        let (bx, by) = Self::bucket(p, self.size);
+        let ((x0, y0), (x1, y1)) = self.extent;
+        let reach = (bx - x0)
+            .abs()
+            .max((bx - x1).abs())
+            .max((by - y0).abs())
+            .max((by - y1).abs());
+        let mut best: Option<(f64, usize)> = None;
+        // Search squares in growing rings until no closer cell can remain
+        for r in 0..=reach {
+            for dx in -r..=r {
+                for dy in -r..=r {
+                    if dx.abs() != r && dy.abs() != r {
+                        continue;
+                    }
+                    for &i in self.buckets.get(&(bx + dx, by + dy)).into_iter().flatten() {
+                        let d = p.dist(self.cells[i].0);
+                        if best.map(|(b, _)| d < b).unwrap_or(true) {
+                            best = Some((d, i));
+                        }
+                    }
+                }
+            }
+            if let Some((d, _)) = best {
+                if d <= r as f64 * self.size {
+                    break;
+                }
+            }
+        }
+        best.map(|(_, i)| self.cells[i].1)
+    }
+}
+
+impl Partition for Automaton {
+    fn index(&self, p: Pos) -> Option<usize> {
+        self.state(p)
+    }
+}
+
+/// Tiles whose cell ended in a given state
+#[derive(Debug)]
+pub struct Cell {
+    pub automaton: Arc<Shared<Automaton>>,
+    pub state: usize,
+    pub color: ColorItem,
+}
+
+impl Contains for Cell {
+    fn hit(&self, p: Pos) -> bool {
+        self.automaton.index(p) == Some(self.state)
+    }
+
+    fn color(&self) -> &ColorItem {
+        &self.color
+    }
 }
@@ -1,5 +1,6 @@
 use crate::prelude::*;
 use crate::svg::*;
+use std::f64::consts::PI;
 
 /// A generic shape that can be placed at any position according to a given center
 pub struct Movable(Vec<Pos>);
@@ -37,6 +38,27 @@ impl Movable {
         Movable(pts)
     }
 
+    /// Rectangle whose long side is oriented along rot
+    pub fn rectangle(length: f64, width: f64, rot: isize) -> Self {
+        let (l, w) = (
+            Pos::polar(rot, length / 2.),
+            Pos::polar(rot + 90, width / 2.),
+        );
+        Movable(vec![l + w, w - l, -l - w, l - w])
+    }
+
+    /// Regular polygon with n sides of the given length, the first one facing rot
+    pub fn regular(n: usize, side: f64, rot: isize) -> Self {
+        let radius = side / 2. / (PI / n as f64).sin();
+        let pts = (0..n)
+            .map(|i| {
+                let theta = radians(rot) + PI * (2 * i + 1) as f64 / n as f64;
+                Pos(radius * theta.cos(), radius * theta.sin())
+            })
+            .collect();
+        Movable(pts)
+    }
+
     pub fn rhombus(ldiag: f64, sdiag: f64, rot: isize) -> Self {
         Movable(vec![
             Pos::polar(rot, ldiag),
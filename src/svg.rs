@@ -2,13 +2,16 @@ use crate::prelude::*;
 use std::fmt;
 use std::io::{self, Write};
 
+#[derive(Clone)]
 pub struct Path {
     pub stroke_width: f64,
     pub stroke_color: Color,
-    pub fill_color: Color,
+    /// None for an outline only
+    pub fill_color: Option<Color>,
     pub data: Data,
 }
 
+#[derive(Clone)]
 pub struct Data(pub Vec<Pos>);
 
 pub struct Document {
@@ -36,13 +39,18 @@ impl Path {
         Self {
             stroke_width: 0.0,
             stroke_color: Color(0, 0, 0),
-            fill_color: Color(255, 255, 255),
+            fill_color: Some(Color(255, 255, 255)),
             data: d,
         }
     }
 
     pub fn with_fill_color(mut self, c: Color) -> Self {
-        self.fill_color = c;
+        self.fill_color = Some(c);
+        self
+    }
+
+    pub fn without_fill(mut self) -> Self {
+        self.fill_color = None;
         self
     }
 
@@ -128,10 +136,15 @@ impl Document {
 
 impl fmt::Display for Path {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<path d=\"{}\" ", self.data)?;
+        match self.fill_color {
+            Some(c) => write!(f, "fill=\"{}\" ", c)?,
+            None => write!(f, "fill=\"none\" ")?,
+        }
         write!(
             f,
-            "<path d=\"{}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"{}\" />",
-            self.data, self.fill_color, self.stroke_color, self.stroke_width
+            "stroke=\"{}\" stroke-width=\"{}\" />",
+            self.stroke_color, self.stroke_width
         )
     }
 }
@@ -1,9 +1,11 @@
+use crate::parallel;
 use crate::prelude::*;
 use crate::shape::*;
 use crate::svg::*;
 use delaunator as del;
-use rand::rngs::ThreadRng;
+use rand::rngs::StdRng;
 use std::collections::HashSet;
+use std::f64::consts::PI;
 
 macro_rules! set {
     { $( $elem:expr ),* } => {
@@ -17,19 +19,18 @@ macro_rules! set {
 
 /// Tile the plane with a pattern that can be mapped to a 2D grid.
 /// This criterion applies to all tilings used here except Delaunay triangulation.
+/// Grid points are found sequentially, the tiles around them are generated in parallel.
 fn periodic_grid_tiling<F>(f: &Frame, gen: F, idir: Pos, jdir: Pos) -> Vec<(Pos, Path)>
 where
-    F: Fn(Pos) -> Vec<(Pos, Path)>,
+    F: Fn(Pos) -> Vec<(Pos, Path)> + Sync,
 {
-    let mut items = Vec::new();
+    let mut points = Vec::new();
     let center = f.center();
     let mut set = set![center];
     let mut stk = vec![center];
     while let Some(pos) = stk.pop() {
         if f.is_inside(pos) {
-            for item in gen(pos) {
-                items.push(item);
-            }
+            points.push(pos);
             for &(i, j) in &[(0, 1), (0, -1), (1, 0), (-1, 0)] {
                 let p = pos + idir * i + jdir * j;
                 if !set.contains(&p) {
@@ -39,7 +40,10 @@ where
             }
         }
     }
-    items
+    parallel::map(&points, |_, &pos| gen(pos))
+        .into_iter()
+        .flatten()
+        .collect()
 }
 
 pub fn tile_hexagons(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
@@ -136,6 +140,258 @@ pub fn tile_rhombus(f: &Frame, ldiag: f64, sdiag: f64, rot: isize) -> Vec<(Pos,
     periodic_grid_tiling(f, |p| vec![m.render(p)], idir, jdir)
 }
 
+/// Position given in units of a along rot and rot + 90
+fn grid_offset(a: f64, rot: isize, x: f64, y: f64) -> Pos {
+    Pos::polar(rot, x * a) + Pos::polar(rot + 90, y * a)
+}
+
+pub fn tile_squares(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
+    let a = size * 2_f64.sqrt();
+    let m = Movable::square(size, rot);
+    periodic_grid_tiling(
+        f,
+        |p| vec![m.render(p)],
+        grid_offset(a, rot, 1., 0.),
+        grid_offset(a, rot, 0., 1.),
+    )
+}
+
+/// Running bond: rows of bricks `ratio` times longer than high,
+/// each row shifted by `offset` times the length of a brick
+pub fn tile_bricks(f: &Frame, size: f64, ratio: f64, offset: f64, rot: isize) -> Vec<(Pos, Path)> {
+    let a = size * 2_f64.sqrt();
+    let m = Movable::rectangle(a * ratio, a, rot);
+    periodic_grid_tiling(
+        f,
+        |p| vec![m.render(p)],
+        grid_offset(a, rot, ratio, 0.),
+        grid_offset(a, rot, offset * ratio, 1.),
+    )
+}
+
+pub fn tile_herringbone(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
+    let a = size * 2_f64.sqrt();
+    //
+    //  +---+
+    //  |   |
+    //  | V |
+    //  |   |
+    //  +---+-------+
+    //  |     H     |
+    //  x-----------+
+    //
+    // Bricks go up in stairs, each stair being a horizontal and a vertical brick
+    let m = [
+        Movable::rectangle(a * 2., a, rot),
+        Movable::rectangle(a * 2., a, rot + 90),
+    ];
+    let adjust = [grid_offset(a, rot, 1., 0.5), grid_offset(a, rot, 0.5, 2.)];
+    periodic_grid_tiling(
+        f,
+        |p| vec![m[0].render(p + adjust[0]), m[1].render(p + adjust[1])],
+        grid_offset(a, rot, 1., 1.),
+        grid_offset(a, rot, 4., 0.),
+    )
+}
+
+pub fn tile_basketweave(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
+    let a = size * 2_f64.sqrt();
+    // Square blocks of two parallel bricks, alternating orientation like a checkerboard
+    let m = [
+        Movable::rectangle(a * 2., a, rot),
+        Movable::rectangle(a * 2., a, rot + 90),
+    ];
+    let adjust = [
+        grid_offset(a, rot, 1., 0.5),
+        grid_offset(a, rot, 1., 1.5),
+        grid_offset(a, rot, 2.5, 1.),
+        grid_offset(a, rot, 3.5, 1.),
+    ];
+    periodic_grid_tiling(
+        f,
+        |p| {
+            vec![
+                m[0].render(p + adjust[0]),
+                m[0].render(p + adjust[1]),
+                m[1].render(p + adjust[2]),
+                m[1].render(p + adjust[3]),
+            ]
+        },
+        grid_offset(a, rot, 2., 2.),
+        grid_offset(a, rot, 2., -2.),
+    )
+}
+
+/// Distance from the center of a regular polygon to its sides
+fn apothem(n: usize, side: f64) -> f64 {
+    side / 2. / (PI / n as f64).tan()
+}
+
+/// Side of a regular polygon from the distance between its center and its corners
+fn side_length(n: usize, radius: f64) -> f64 {
+    2. * radius * (PI / n as f64).sin()
+}
+
+/// 4.8.8: octagons on a square grid, with squares in between
+pub fn tile_octagons_squares(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
+    let side = side_length(8, size);
+    let (r8, r4) = (apothem(8, side), apothem(4, side));
+    let m = [
+        Movable::regular(8, side, rot),
+        Movable::regular(4, side, rot + 225),
+    ];
+    let adjust = Pos::polar(rot + 45, r8 + r4);
+    periodic_grid_tiling(
+        f,
+        |p| vec![m[0].render(p), m[1].render(p + adjust)],
+        Pos::polar(rot, 2. * r8),
+        Pos::polar(rot + 90, 2. * r8),
+    )
+}
+
+/// 4.6.12: dodecagons separated by squares, with hexagons in the gaps
+pub fn tile_dodecagons_hexagons_squares(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
+    let side = side_length(12, size);
+    let (r12, r6, r4) = (apothem(12, side), apothem(6, side), apothem(4, side));
+    let m = Movable::regular(12, side, rot);
+    let squares = (0..3)
+        .map(|i| {
+            (
+                Movable::regular(4, side, rot + 60 * i),
+                Pos::polar(rot + 60 * i, r12 + r4),
+            )
+        })
+        .collect::<Vec<_>>();
+    let hexagons = (0..2)
+        .map(|i| {
+            (
+                Movable::regular(6, side, rot + 30),
+                Pos::polar(rot + 30 + 60 * i, r12 + r6),
+            )
+        })
+        .collect::<Vec<_>>();
+    periodic_grid_tiling(
+        f,
+        |p| {
+            let mut items = vec![m.render(p)];
+            for (mv, adjust) in squares.iter().chain(hexagons.iter()) {
+                items.push(mv.render(p + *adjust));
+            }
+            items
+        },
+        Pos::polar(rot, 2. * (r12 + r4)),
+        Pos::polar(rot + 60, 2. * (r12 + r4)),
+    )
+}
+
+/// 3.12.12: dodecagons side by side, with triangles in the gaps
+pub fn tile_dodecagons_triangles(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
+    let side = side_length(12, size);
+    let (r12, r3) = (apothem(12, side), apothem(3, side));
+    let m = [
+        Movable::regular(12, side, rot),
+        Movable::regular(3, side, rot + 210),
+        Movable::regular(3, side, rot + 270),
+    ];
+    let adjust = [
+        Pos::polar(rot + 30, r12 + r3),
+        Pos::polar(rot + 90, r12 + r3),
+    ];
+    periodic_grid_tiling(
+        f,
+        |p| {
+            vec![
+                m[0].render(p),
+                m[1].render(p + adjust[0]),
+                m[2].render(p + adjust[1]),
+            ]
+        },
+        Pos::polar(rot, 2. * r12),
+        Pos::polar(rot + 60, 2. * r12),
+    )
+}
+
+/// 3.3.3.4.4: rows of squares alternating with rows of triangles
+pub fn tile_elongated_triangles(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
+    let side = side_length(4, size);
+    let h = side * radians(60).sin();
+    let r3 = apothem(3, side);
+    let m = [
+        Movable::regular(4, side, rot),
+        Movable::regular(3, side, rot + 270),
+        Movable::regular(3, side, rot + 90),
+    ];
+    let adjust = [
+        grid_offset(side, rot, 0., 0.5) + Pos::polar(rot + 90, r3),
+        grid_offset(side, rot, 0.5, 0.5) + Pos::polar(rot + 90, h - r3),
+    ];
+    periodic_grid_tiling(
+        f,
+        |p| {
+            vec![
+                m[0].render(p),
+                m[1].render(p + adjust[0]),
+                m[2].render(p + adjust[1]),
+            ]
+        },
+        grid_offset(side, rot, 1., 0.),
+        grid_offset(side, rot, 0.5, 1.) + Pos::polar(rot + 90, h),
+    )
+}
+
+/// 3.3.4.3.4: squares tilted both ways, each surrounded by triangles
+pub fn tile_snub_squares(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
+    let side = side_length(4, size);
+    let (r4, r3) = (apothem(4, side), apothem(3, side));
+    // Side of the grid, such that neighboring squares share a corner
+    let a = 2. * side * radians(15).cos();
+    let mut mv = vec![
+        (Movable::regular(4, side, rot + 15), Pos::zero()),
+        (
+            Movable::regular(4, side, rot - 15),
+            grid_offset(a, rot, 0.5, 0.5),
+        ),
+    ];
+    for i in 0..4 {
+        mv.push((
+            Movable::regular(3, side, rot + 195 + 90 * i),
+            Pos::polar(rot + 15 + 90 * i, r4 + r3),
+        ));
+    }
+    periodic_grid_tiling(
+        f,
+        |p| mv.iter().map(|(m, adjust)| m.render(p + *adjust)).collect(),
+        grid_offset(a, rot, 1., 0.),
+        grid_offset(a, rot, 0., 1.),
+    )
+}
+
+/// 3.3.3.3.6: hexagons each surrounded by triangles, all vertices lie on a grid of triangles
+pub fn tile_snub_hexagons(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
+    let side = side_length(6, size);
+    let (r6, r3) = (apothem(6, side), apothem(3, side));
+    // Sides of the underlying grid of triangles
+    let a = Pos::polar(rot + 30, side);
+    let b = Pos::polar(rot + 90, side);
+    let c = Pos::polar(rot + 150, side);
+    let mut mv = vec![(Movable::regular(6, side, rot), Pos::zero())];
+    for i in 0..6 {
+        mv.push((
+            Movable::regular(3, side, rot + 180 + 60 * i),
+            Pos::polar(rot + 60 * i, r6 + r3),
+        ));
+    }
+    // Triangles that only touch hexagons by their corners
+    mv.push((Movable::regular(3, side, rot + 60), (a + b * 4) * (1. / 3.)));
+    mv.push((Movable::regular(3, side, rot), (a + b * 4) * (2. / 3.)));
+    periodic_grid_tiling(
+        f,
+        |p| mv.iter().map(|(m, adjust)| m.render(p + *adjust)).collect(),
+        a * 2 + b,
+        b * 2 + c,
+    )
+}
+
 /// External crate does the heavy lifting and is an order of magnitude faster than the previously implemented Boyer-Watson algorithm.
 /// Only downside is that it requires conversions between position types.
 fn fast_triangulate(pts: &[Pos]) -> Vec<(Pos, Pos, Pos)> {
@@ -156,7 +412,7 @@ fn fast_triangulate(pts: &[Pos]) -> Vec<(Pos, Pos, Pos)> {
     v
 }
 
-pub fn random_delaunay(f: &Frame, rng: &mut ThreadRng, n: usize) -> Vec<(Pos, Path)> {
+pub fn random_delaunay(f: &Frame, rng: &mut StdRng, n: usize) -> Vec<(Pos, Path)> {
     let mut pts = Vec::new();
     for _ in 0..n {
         pts.push(Pos::random(f, rng));
@@ -353,7 +609,7 @@ pub fn pentagons_type5(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
     )
 }
 
-pub fn pentagons_type6(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
+pub fn pentagons_type6(f: &Frame, size: f64, _rot: isize) -> Vec<(Pos, Path)> {
     let rot = 60;
     let alpha = 150;
     let beta = 44;